@@ -0,0 +1,24 @@
+//! Compare `cargo bench --bench from_leaves` with `cargo bench --bench from_leaves --features rayon`
+//! to measure the speedup of the parallel build.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use noir_merkle_tree::merkle::HybridMerkleTree;
+use starknet_types_core::felt::Felt;
+
+fn bench_from_leaves(c: &mut Criterion) {
+    let mut group = c.benchmark_group("from_leaves");
+    group.sample_size(10);
+    for log_leaves in [12u32, 16] {
+        let leaves: Vec<Felt> = (0..1u64 << log_leaves).map(Felt::from).collect();
+        let height = log_leaves as usize + 1;
+        group.bench_with_input(
+            BenchmarkId::from_parameter(leaves.len()),
+            &leaves,
+            |b, leaves| b.iter(|| HybridMerkleTree::from_leaves(height, leaves).unwrap()),
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_from_leaves);
+criterion_main!(benches);
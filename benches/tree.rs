@@ -0,0 +1,103 @@
+//! Run a single scenario with `cargo bench --bench tree -- add_leaf` (or `path`, `bulk_add`, `new`, `small_trees`).
+//! `from_leaves` has its own bench target.
+
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion};
+use noir_merkle_tree::merkle::{HybridMerkleTree, LeafIndex};
+use starknet_types_core::felt::Felt;
+
+/// Leaves already in the tree before measuring, so later levels are not all empty.
+const PREFILLED: u64 = 1 << 10;
+
+fn prefilled_tree(height: usize) -> HybridMerkleTree {
+    let leaves: Vec<Felt> = (0..PREFILLED).map(Felt::from).collect();
+    HybridMerkleTree::from_leaves(height, &leaves).unwrap()
+}
+
+fn bench_add_leaf(c: &mut Criterion) {
+    let mut group = c.benchmark_group("add_leaf");
+    for height in [16usize, 24, 32] {
+        let tree = prefilled_tree(height);
+        let leaf = Felt::from(PREFILLED);
+        group.bench_with_input(BenchmarkId::from_parameter(height), &tree, |b, tree| {
+            b.iter_batched_ref(
+                || tree.clone(),
+                |tree| tree.add_leaf(&leaf).unwrap(),
+                BatchSize::SmallInput,
+            )
+        });
+    }
+    group.finish();
+}
+
+fn bench_path(c: &mut Criterion) {
+    let mut group = c.benchmark_group("path");
+    for height in [16usize, 24, 32] {
+        let tree = prefilled_tree(height);
+        let index = PREFILLED as usize / 2;
+        group.bench_with_input(BenchmarkId::from_parameter(height), &tree, |b, tree| {
+            b.iter(|| tree.path(LeafIndex(index)).unwrap())
+        });
+    }
+    group.finish();
+}
+
+/// Appends `BULK_LEAVES` leaves to an empty tree, with and without reserving the layers first.
+fn bench_bulk_add(c: &mut Criterion) {
+    const BULK_LEAVES: u64 = 1 << 12;
+    let leaves: Vec<Felt> = (0..BULK_LEAVES).map(Felt::from).collect();
+    let mut group = c.benchmark_group("bulk_add");
+    group.bench_function("new", |b| {
+        b.iter(|| {
+            let mut tree = HybridMerkleTree::new(24);
+            tree.add_leaves(&leaves).unwrap();
+            tree
+        })
+    });
+    group.bench_function("with_capacity", |b| {
+        b.iter(|| {
+            let mut tree = HybridMerkleTree::with_capacity(24, leaves.len());
+            tree.add_leaves(&leaves).unwrap();
+            tree
+        })
+    });
+    group.finish();
+}
+
+/// Builds an empty height-32 tree over the default null base, whose null hashes are cached
+/// after the first tree, and over another base, which hashes every level again.
+fn bench_new(c: &mut Criterion) {
+    let mut group = c.benchmark_group("new");
+    group.bench_function("default_base", |b| b.iter(|| HybridMerkleTree::new(32)));
+    group.bench_function("custom_base", |b| {
+        b.iter(|| HybridMerkleTree::with_null_base(32, Felt::ZERO))
+    });
+    group.finish();
+}
+
+/// Builds `SMALL_TREES` height-32 trees of 4 leaves each, the case where per-level
+/// allocations dominate. `VecStore::allocated_nodes` reports the memory they hold.
+fn bench_small_trees(c: &mut Criterion) {
+    const SMALL_TREES: usize = 1000;
+    let leaves: Vec<Felt> = (1..=4u64).map(Felt::from).collect();
+    c.bench_function("small_trees", |b| {
+        b.iter(|| {
+            (0..SMALL_TREES)
+                .map(|_| {
+                    let mut tree = HybridMerkleTree::new(32);
+                    tree.add_leaves(&leaves).unwrap();
+                    tree
+                })
+                .collect::<Vec<_>>()
+        })
+    });
+}
+
+criterion_group!(
+    benches,
+    bench_add_leaf,
+    bench_path,
+    bench_bulk_add,
+    bench_new,
+    bench_small_trees
+);
+criterion_main!(benches);
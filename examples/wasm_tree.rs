@@ -0,0 +1,22 @@
+//! Drives the WASM bindings natively. In the browser the same calls are made from JS:
+//!
+//! ```js
+//! const tree = new WasmMerkleTree(3);
+//! tree.add_leaf("0x1");
+//! console.log(tree.root_hex(), tree.path_json(0));
+//! ```
+
+use noir_merkle_tree::wasm::WasmMerkleTree;
+
+fn main() {
+    let mut tree = WasmMerkleTree::new(3).expect("height is at least 1");
+    for leaf in ["0x1", "0x2", "0x3"] {
+        let index = tree.add_leaf(leaf).expect("leaf is valid hex");
+        println!("added {} at index {}", leaf, index);
+    }
+    println!("root: {}", tree.root_hex());
+    println!(
+        "path of leaf 0: {}",
+        tree.path_json(0).expect("leaf 0 exists")
+    );
+}
@@ -0,0 +1,55 @@
+//! Inserts an arbitrary sequence of leaves into a tree of arbitrary height and checks, after
+//! every insertion, that the proof of each leaf verifies against the current root.
+//!
+//! Run with `cargo fuzz run add_leaf_path` from the repository root. A crashing input is
+//! written to `fuzz/artifacts/add_leaf_path/`; `cargo fuzz tmin add_leaf_path <artifact>`
+//! shrinks it to a minimal failing insertion sequence.
+//!
+//! The fuzz crate is its own workspace, so builds of the main crate do not cover it; check it
+//! with `cargo check --manifest-path fuzz/Cargo.toml` after changing the public API.
+
+#![no_main]
+
+use arbitrary::Arbitrary;
+use libfuzzer_sys::fuzz_target;
+use noir_merkle_tree::error::MerkleError;
+use noir_merkle_tree::merkle::{HybridMerkleTree, LeafIndex};
+use starknet_types_core::felt::Felt;
+
+/// Tallest tree tried, so that the quadratic proof checks stay fast.
+const MAX_HEIGHT: u8 = 10;
+
+#[derive(Debug, Arbitrary)]
+struct Input {
+    height: u8,
+    leaves: Vec<[u8; 32]>,
+}
+
+fuzz_target!(|input: Input| {
+    let height = 1 + (input.height % MAX_HEIGHT) as usize;
+    let mut tree = HybridMerkleTree::new(height);
+    let mut inserted = Vec::new();
+    for bytes in &input.leaves {
+        let leaf = Felt::from_bytes_be(bytes);
+        match tree.add_leaf(&leaf) {
+            Ok(index) => assert_eq!(index, inserted.len()),
+            Err(MerkleError::TreeFull { capacity }) => {
+                assert_eq!(capacity, inserted.len());
+                break;
+            }
+            Err(e) => panic!("unexpected error: {}", e),
+        }
+        inserted.push(leaf);
+
+        let root = tree.root();
+        for (index, leaf) in inserted.iter().enumerate() {
+            let proof = tree.proof(LeafIndex(index)).unwrap();
+            assert_eq!(proof.leaf, *leaf);
+            assert!(proof.verify(&root), "leaf {} of {}", index, inserted.len());
+        }
+    }
+    assert_eq!(
+        tree.root(),
+        HybridMerkleTree::root_of(height, &inserted).unwrap()
+    );
+});
@@ -0,0 +1,162 @@
+use core::marker::PhantomData;
+
+use starknet_types_core::felt::Felt;
+
+use crate::hasher::{MerkleHasher, PoseidonHasher};
+use crate::helpers::default_null_base;
+use crate::merkle::{HybridMerkleTree, Padding, SiblingOrder};
+
+/// MerkleTreeBuilder combines the optional features of a `HybridMerkleTree` before it is
+/// created. Start from `HybridMerkleTree::builder()`, or from
+/// `MerkleTreeBuilder::<H>::default()` for another hasher.
+#[derive(Debug, Clone)]
+pub struct MerkleTreeBuilder<H: MerkleHasher = PoseidonHasher> {
+    height: Option<usize>,
+    null_base: Felt,
+    root_history: bool,
+    index_map: bool,
+    reject_duplicates: bool,
+    leaf_domain: Option<Felt>,
+    padding: Padding,
+    sibling_order: SiblingOrder,
+    hasher: PhantomData<H>,
+}
+
+impl<H: MerkleHasher> Default for MerkleTreeBuilder<H> {
+    fn default() -> Self {
+        Self {
+            height: None,
+            null_base: default_null_base(),
+            root_history: false,
+            index_map: false,
+            reject_duplicates: false,
+            leaf_domain: None,
+            padding: Padding::Right,
+            sibling_order: SiblingOrder::Positional,
+            hasher: PhantomData,
+        }
+    }
+}
+
+impl<H: MerkleHasher> MerkleTreeBuilder<H> {
+    /// Sets the number of levels of the tree, leaves and root included.
+    pub fn height(mut self, height: usize) -> Self {
+        self.height = Some(height);
+        self
+    }
+
+    /// Sets the value empty leaves hash from, instead of the default.
+    pub fn null_base(mut self, base: Felt) -> Self {
+        self.null_base = base;
+        self
+    }
+
+    /// See `HybridMerkleTree::with_root_history`.
+    pub fn with_root_history(mut self) -> Self {
+        self.root_history = true;
+        self
+    }
+
+    /// See `HybridMerkleTree::with_index_map`.
+    pub fn with_index_map(mut self) -> Self {
+        self.index_map = true;
+        self
+    }
+
+    /// See `HybridMerkleTree::with_reject_duplicates`.
+    pub fn with_reject_duplicates(mut self) -> Self {
+        self.reject_duplicates = true;
+        self
+    }
+
+    /// See `HybridMerkleTree::with_leaf_domain`.
+    pub fn leaf_domain(mut self, domain: Felt) -> Self {
+        self.leaf_domain = Some(domain);
+        self
+    }
+
+    /// Sets the side on which the tree is padded with null hashes, `Padding::Right` by default.
+    pub fn padding(mut self, padding: Padding) -> Self {
+        self.padding = padding;
+        self
+    }
+
+    /// Sets the order in which siblings are hashed, `SiblingOrder::Positional` by default.
+    pub fn sibling_order(mut self, order: SiblingOrder) -> Self {
+        self.sibling_order = order;
+        self
+    }
+
+    /// Creates the empty tree. Panics if no height was set.
+    pub fn build(self) -> HybridMerkleTree<H> {
+        let height = self.height.expect("Tree height must be set!");
+        let mut tree = HybridMerkleTree::with_hasher_and_null_base(height, self.null_base)
+            .with_padding(self.padding)
+            .with_sibling_order(self.sibling_order);
+        if self.root_history {
+            tree = tree.with_root_history();
+        }
+        if self.index_map {
+            tree = tree.with_index_map();
+        }
+        if self.reject_duplicates {
+            tree = tree.with_reject_duplicates();
+        }
+        if let Some(domain) = self.leaf_domain {
+            tree = tree.with_leaf_domain(domain);
+        }
+        tree
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hasher::PedersenHasher;
+    use crate::helpers::{compute_merkle_root_with_domain, precomputed_hashes, tag_leaf};
+    use crate::merkle::LeafIndex;
+
+    #[test]
+    fn test_builder_combines_options() {
+        let base = Felt::from(5);
+        let domain = Felt::from(9);
+        let mut tree = HybridMerkleTree::builder()
+            .height(4)
+            .null_base(base)
+            .with_root_history()
+            .with_index_map()
+            .leaf_domain(domain)
+            .build();
+        assert_eq!(tree.height(), 4);
+        assert_eq!(
+            tree.root(),
+            precomputed_hashes::<PoseidonHasher>(4, base)[3]
+        );
+
+        let empty_root = tree.root();
+        let leaf = Felt::from(1);
+        tree.add_leaf(&leaf).unwrap();
+        assert_eq!(tree.root_at(0), Some(empty_root));
+        assert_eq!(tree.root_at(1), Some(tree.root()));
+
+        let tagged = tag_leaf::<PoseidonHasher>(&leaf, Some(&domain));
+        assert_eq!(tree.index_of(&tagged), Some(0));
+        let (path, _bits) = tree.path(LeafIndex(0)).unwrap();
+        assert_eq!(
+            compute_merkle_root_with_domain::<PoseidonHasher>(leaf, Some(&domain), 0, &path),
+            tree.root()
+        );
+    }
+
+    #[test]
+    fn test_builder_defaults_match_new() {
+        let tree = MerkleTreeBuilder::<PedersenHasher>::default()
+            .height(3)
+            .build();
+        assert_eq!(
+            tree.root(),
+            HybridMerkleTree::<PedersenHasher>::with_hasher(3).root()
+        );
+        assert_eq!(tree.root_at(0), None);
+    }
+}
@@ -0,0 +1,196 @@
+use alloc::vec::Vec;
+use core::ops::Range;
+
+use starknet_types_core::felt::Felt;
+
+use crate::error::MerkleError;
+use crate::hasher::{MerkleHasher, PoseidonHasher};
+use crate::merkle::{HybridMerkleTree, LeafIndex, MerkleProof};
+
+/// DeferredMerkleTree buffers added leaves and hashes them into a `HybridMerkleTree` in a
+/// single pass the next time the root or a proof is needed, instead of rehashing the path to
+/// the root on every insertion. Suited to inserting many leaves and then querying once: each
+/// changed node is hashed once per flush instead of once per leaf below it.
+#[derive(Debug, Clone)]
+pub struct DeferredMerkleTree<H: MerkleHasher = PoseidonHasher> {
+    tree: HybridMerkleTree<H>,
+    pending: Vec<Felt>,
+}
+
+impl DeferredMerkleTree {
+    pub fn new(height: usize) -> Self {
+        Self::with_hasher(height)
+    }
+
+    /// Creates an empty tree whose empty leaves hash from `base` instead of the default.
+    pub fn with_null_base(height: usize, base: Felt) -> Self {
+        Self::with_hasher_and_null_base(height, base)
+    }
+}
+
+impl<H: MerkleHasher> DeferredMerkleTree<H> {
+    /// Same as `new`, for a tree combining nodes with `H`.
+    pub fn with_hasher(height: usize) -> Self {
+        HybridMerkleTree::with_hasher(height).into()
+    }
+
+    /// Same as `with_null_base`, for a tree combining nodes with `H`.
+    pub fn with_hasher_and_null_base(height: usize, base: Felt) -> Self {
+        HybridMerkleTree::with_hasher_and_null_base(height, base).into()
+    }
+
+    /// Buffers a new leaf without hashing it. Returns the index assigned to the leaf.
+    pub fn add_leaf(&mut self, leaf: &Felt) -> Result<usize, MerkleError> {
+        let capacity = self.tree.capacity();
+        if self.len() >= capacity {
+            return Err(MerkleError::TreeFull { capacity });
+        }
+        self.pending.push(*leaf);
+        Ok(self.len() - 1)
+    }
+
+    /// Buffers `leaves` in order and returns the range of indices they were assigned. Nothing
+    /// is buffered if they do not all fit.
+    pub fn add_leaves(&mut self, leaves: &[Felt]) -> Result<Range<usize>, MerkleError> {
+        let capacity = self.tree.capacity();
+        if leaves.len() > capacity - self.len() {
+            return Err(MerkleError::TreeFull { capacity });
+        }
+        let start = self.len();
+        self.pending.extend_from_slice(leaves);
+        Ok(start..self.len())
+    }
+
+    /// Number of leaves added so far, hashed or not.
+    pub fn len(&self) -> usize {
+        self.tree.len() + self.pending.len()
+    }
+
+    /// Returns `true` if no leaf has been added yet.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns `true` if some leaves are buffered and not hashed into the tree yet.
+    pub fn is_dirty(&self) -> bool {
+        !self.pending.is_empty()
+    }
+
+    /// Hashes the buffered leaves into the tree and returns it, up to date.
+    pub fn flush(&mut self) -> &HybridMerkleTree<H> {
+        if self.is_dirty() {
+            self.tree.append_batch(&self.pending);
+            self.pending.clear();
+        }
+        &self.tree
+    }
+
+    /// Returns the current tree root, flushing the buffered leaves first.
+    pub fn root(&mut self) -> Felt {
+        self.flush().root()
+    }
+
+    /// See `HybridMerkleTree::path`; flushes the buffered leaves first.
+    pub fn path(&mut self, index: LeafIndex) -> Result<(Vec<Felt>, Vec<bool>), MerkleError> {
+        self.flush().path(index)
+    }
+
+    /// See `HybridMerkleTree::proof`; flushes the buffered leaves first.
+    pub fn proof(&mut self, index: LeafIndex) -> Result<MerkleProof, MerkleError> {
+        self.flush().proof(index)
+    }
+
+    /// Flushes the buffered leaves and returns the underlying tree.
+    pub fn into_inner(mut self) -> HybridMerkleTree<H> {
+        self.flush();
+        self.tree
+    }
+}
+
+/// Buffers further leaves on top of `tree`, keeping its configuration.
+impl<H: MerkleHasher> From<HybridMerkleTree<H>> for DeferredMerkleTree<H> {
+    fn from(tree: HybridMerkleTree<H>) -> Self {
+        Self {
+            tree,
+            pending: Vec::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_deferred_matches_eager() {
+        let leaves: Vec<Felt> = (1..=100u32).map(Felt::from).collect();
+        let mut eager = HybridMerkleTree::new(8);
+        let mut deferred = DeferredMerkleTree::new(8);
+        assert_eq!(deferred.root(), eager.root());
+
+        // Flushing at uneven points rehashes from the middle of partially filled layers.
+        for chunk in leaves.chunks(13) {
+            for leaf in chunk {
+                assert_eq!(deferred.add_leaf(leaf), eager.add_leaf(leaf));
+            }
+            assert!(deferred.is_dirty());
+            assert_eq!(deferred.root(), eager.root());
+            assert!(!deferred.is_dirty());
+        }
+        for index in [0, 37, 99] {
+            assert_eq!(
+                deferred.proof(LeafIndex(index)),
+                eager.proof(LeafIndex(index))
+            );
+        }
+        assert_eq!(deferred.path(LeafIndex(64)), eager.path(LeafIndex(64)));
+        assert_eq!(deferred.len(), 100);
+        assert_eq!(deferred.into_inner(), eager);
+    }
+
+    #[test]
+    fn test_deferred_keeps_tree_configuration() {
+        use crate::merkle::Padding;
+
+        let leaves: Vec<Felt> = (1..=6u32).map(Felt::from).collect();
+        let mut eager = HybridMerkleTree::builder()
+            .height(4)
+            .padding(Padding::Left)
+            .leaf_domain(Felt::from(7))
+            .with_index_map()
+            .build();
+        let mut deferred = DeferredMerkleTree::from(eager.clone());
+        eager.add_leaves(&leaves).unwrap();
+        assert_eq!(deferred.add_leaves(&leaves), Ok(0..6));
+        let tree = deferred.flush();
+        assert_eq!(tree.root(), eager.root());
+        assert_eq!(tree.proof(LeafIndex(3)), eager.proof(LeafIndex(3)));
+        let leaf = *eager.leaves().nth(4).unwrap();
+        assert_eq!(tree.index_of(&leaf), Some(4));
+    }
+
+    #[test]
+    fn test_deferred_tree_full() {
+        let mut tree = DeferredMerkleTree::new(2);
+        assert_eq!(tree.add_leaves(&[Felt::ONE, Felt::TWO]), Ok(0..2));
+        assert_eq!(
+            tree.add_leaf(&Felt::THREE),
+            Err(MerkleError::TreeFull { capacity: 2 })
+        );
+        assert_eq!(
+            tree.add_leaves(&[Felt::THREE]),
+            Err(MerkleError::TreeFull { capacity: 2 })
+        );
+        assert_eq!(tree.len(), 2);
+    }
+
+    #[cfg(feature = "metrics")]
+    #[test]
+    fn test_deferred_hashes_each_node_once() {
+        let leaves: Vec<Felt> = (1..=8u32).map(Felt::from).collect();
+        let mut tree = DeferredMerkleTree::new(4);
+        tree.add_leaves(&leaves).unwrap();
+        // 4 + 2 + 1 parents, where eager insertion costs 3 hashes per leaf.
+        assert_eq!(tree.flush().hash_count(), 7);
+    }
+}
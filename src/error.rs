@@ -0,0 +1,131 @@
+use alloc::string::String;
+use core::fmt;
+
+use starknet_types_core::felt::Felt;
+
+/// Errors returned by fallible tree operations.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MerkleError {
+    /// No leaf has been added at `index`; the tree currently holds `len` leaves.
+    LeafNotFound { index: usize, len: usize },
+    /// The tree already holds `capacity` leaves.
+    TreeFull { capacity: usize },
+    /// A proof carries `actual` siblings where `expected` are required.
+    ProofLengthMismatch { expected: usize, actual: usize },
+    /// `index` does not fit in a tree that holds at most `capacity` leaves.
+    IndexOutOfRange { index: usize, capacity: usize },
+    /// The tree does not retain the nodes needed to build the path of leaf `index`.
+    PathUnavailable { index: usize },
+    /// A checkpoint taken at `checkpoint_len` leaves cannot be restored on a tree of `len` leaves.
+    InvalidCheckpoint { checkpoint_len: usize, len: usize },
+    /// The value being inserted or proven absent is already stored at leaf `index`.
+    ValueAlreadyPresent { index: usize },
+    /// A tree rejecting duplicates already holds the inserted leaf at `index`.
+    DuplicateLeaf { index: usize },
+    /// A byte-encoded proof is `actual` bytes long where `expected` are required.
+    InvalidProofEncoding { expected: usize, actual: usize },
+    /// A tree needs at least one level; `height` is 0.
+    InvalidHeight { height: usize },
+    /// A tree of `len` leaves cannot be truncated to the larger `new_len`.
+    TruncateOutOfRange { new_len: usize, len: usize },
+    /// The leaf range `start..end` is empty.
+    InvalidRange { start: usize, end: usize },
+    /// `value` is neither a hex nor a decimal field element below the modulus.
+    InvalidFelt { value: String },
+    /// A sparse tree key does not fit in the `bits` bits the tree walks.
+    KeyOutOfRange { key: Felt, bits: usize },
+    /// Level `level` of a proof is corrupt: its side indicator disagrees with the index, or
+    /// its sibling is zero above the leaf level.
+    MalformedProof { level: usize },
+    /// A tree of height `actual` was given where one of height `expected` is required.
+    HeightMismatch { expected: usize, actual: usize },
+    /// A JSON-encoded proof does not follow the schema of `MerkleProof::to_json`.
+    InvalidProofJson { reason: String },
+    /// A subtree of `leaves` leaves cannot be grafted at leaf `index`, which is not a multiple
+    /// of `leaves`.
+    MisalignedSubtree { index: usize, leaves: usize },
+    /// The operation does not support the tree's configuration, described by `reason`.
+    UnsupportedConfiguration { reason: &'static str },
+}
+
+impl fmt::Display for MerkleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MerkleError::LeafNotFound { index, len } => {
+                write!(f, "leaf {} does not exist (tree has {} leaves)", index, len)
+            }
+            MerkleError::TreeFull { capacity } => {
+                write!(f, "tree is full (capacity {} leaves)", capacity)
+            }
+            MerkleError::ProofLengthMismatch { expected, actual } => {
+                write!(f, "proof has {} siblings, expected {}", actual, expected)
+            }
+            MerkleError::IndexOutOfRange { index, capacity } => {
+                write!(
+                    f,
+                    "index {} is out of range (capacity {} leaves)",
+                    index, capacity
+                )
+            }
+            MerkleError::PathUnavailable { index } => {
+                write!(f, "path for leaf {} is not retained", index)
+            }
+            MerkleError::InvalidCheckpoint {
+                checkpoint_len,
+                len,
+            } => write!(
+                f,
+                "cannot roll back to a checkpoint of {} leaves from {} leaves",
+                checkpoint_len, len
+            ),
+            MerkleError::ValueAlreadyPresent { index } => {
+                write!(f, "value is already present at leaf {}", index)
+            }
+            MerkleError::DuplicateLeaf { index } => {
+                write!(f, "leaf is already present at index {}", index)
+            }
+            MerkleError::InvalidProofEncoding { expected, actual } => {
+                write!(
+                    f,
+                    "encoded proof has {} bytes, expected {}",
+                    actual, expected
+                )
+            }
+            MerkleError::InvalidHeight { height } => {
+                write!(f, "tree height must be at least 1, got {}", height)
+            }
+            MerkleError::TruncateOutOfRange { new_len, len } => {
+                write!(f, "cannot truncate a tree of {} leaves to {}", len, new_len)
+            }
+            MerkleError::InvalidRange { start, end } => {
+                write!(f, "leaf range {}..{} is empty", start, end)
+            }
+            MerkleError::InvalidFelt { value } => {
+                write!(f, "{:?} is not a valid field element", value)
+            }
+            MerkleError::KeyOutOfRange { key, bits } => {
+                write!(f, "key {:#x} does not fit in {} bits", key, bits)
+            }
+            MerkleError::MalformedProof { level } => {
+                write!(f, "proof is malformed at level {}", level)
+            }
+            MerkleError::HeightMismatch { expected, actual } => {
+                write!(f, "tree has height {}, expected {}", actual, expected)
+            }
+            MerkleError::InvalidProofJson { reason } => {
+                write!(f, "invalid proof JSON: {}", reason)
+            }
+            MerkleError::MisalignedSubtree { index, leaves } => write!(
+                f,
+                "a subtree of {} leaves cannot start at leaf {}",
+                leaves, index
+            ),
+            MerkleError::UnsupportedConfiguration { reason } => {
+                write!(f, "unsupported tree configuration: {}", reason)
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for MerkleError {}
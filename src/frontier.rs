@@ -0,0 +1,403 @@
+use alloc::vec::Vec;
+use core::marker::PhantomData;
+
+use starknet_types_core::felt::Felt;
+
+use crate::error::MerkleError;
+use crate::hasher::{MerkleHasher, PoseidonHasher};
+use crate::helpers::{assert_height, check_height, default_null_base, precomputed_hashes};
+
+/// FrontierMerkleTree is an append-only accumulator that keeps only the rightmost path
+/// (`left_path`) instead of every layer, so memory stays O(height) regardless of leaf count.
+/// It computes the same roots as `HybridMerkleTree`, but can only produce the path of the
+/// most recently added leaf; older paths require the full layers.
+#[derive(Debug, Clone)]
+pub struct FrontierMerkleTree<H: MerkleHasher = PoseidonHasher> {
+    height: usize,
+    precomputed: Vec<Felt>,
+    left_path: Vec<Felt>,
+    free_index: usize, // Number of leaves added.
+    hasher: PhantomData<H>,
+}
+
+impl FrontierMerkleTree {
+    pub fn new(height: usize) -> Self {
+        Self::with_hasher(height)
+    }
+
+    /// Creates an empty tree whose empty leaves hash from `base` instead of the default.
+    pub fn with_null_base(height: usize, base: Felt) -> Self {
+        Self::with_hasher_and_null_base(height, base)
+    }
+
+    /// Resumes a tree from the `frontier` of a tree of `free_index` leaves, as exported by
+    /// `frontier` or `HybridMerkleTree::frontier` (with the default padding and sibling order).
+    /// Fails with `HeightMismatch` unless the frontier has one node per level. The frontier
+    /// does not carry the null base: resume trees with another base with
+    /// `from_frontier_with_null_base`, or their roots will be wrong.
+    pub fn from_frontier(
+        height: usize,
+        free_index: usize,
+        frontier: &[Felt],
+    ) -> Result<Self, MerkleError> {
+        Self::from_frontier_with_hasher(height, free_index, frontier)
+    }
+
+    /// Same as `from_frontier`, for a frontier exported from a tree whose empty leaves hash
+    /// from `base`.
+    pub fn from_frontier_with_null_base(
+        height: usize,
+        free_index: usize,
+        frontier: &[Felt],
+        base: Felt,
+    ) -> Result<Self, MerkleError> {
+        Self::from_frontier_with_hasher_and_null_base(height, free_index, frontier, base)
+    }
+}
+
+impl<H: MerkleHasher> FrontierMerkleTree<H> {
+    /// Same as `new`, for a tree combining nodes with `H`.
+    pub fn with_hasher(height: usize) -> Self {
+        Self::with_hasher_and_null_base(height, default_null_base())
+    }
+
+    /// Same as `from_frontier`, for a tree combining nodes with `H`.
+    pub fn from_frontier_with_hasher(
+        height: usize,
+        free_index: usize,
+        frontier: &[Felt],
+    ) -> Result<Self, MerkleError> {
+        Self::from_frontier_with_hasher_and_null_base(
+            height,
+            free_index,
+            frontier,
+            default_null_base(),
+        )
+    }
+
+    /// Same as `from_frontier_with_null_base`, for a tree combining nodes with `H`.
+    pub fn from_frontier_with_hasher_and_null_base(
+        height: usize,
+        free_index: usize,
+        frontier: &[Felt],
+        base: Felt,
+    ) -> Result<Self, MerkleError> {
+        check_height(height)?;
+        if frontier.len() != height {
+            return Err(MerkleError::HeightMismatch {
+                expected: height,
+                actual: frontier.len(),
+            });
+        }
+        let mut tree = Self::with_hasher_and_null_base(height, base);
+        let capacity = tree.capacity();
+        if free_index > capacity {
+            return Err(MerkleError::TreeFull { capacity });
+        }
+        tree.left_path.copy_from_slice(frontier);
+        tree.free_index = free_index;
+        Ok(tree)
+    }
+
+    /// Same as `with_null_base`, for a tree combining nodes with `H`.
+    /// Panics if `height` is 0.
+    pub fn with_hasher_and_null_base(height: usize, base: Felt) -> Self {
+        assert_height(height);
+        let precomputed = precomputed_hashes::<H>(height, base);
+        let left_path = precomputed.clone();
+        Self {
+            height,
+            precomputed,
+            left_path,
+            free_index: 0,
+            hasher: PhantomData,
+        }
+    }
+
+    /// Adds a new leaf in O(height) and returns its index.
+    pub fn add_leaf(&mut self, leaf: &Felt) -> Result<usize, MerkleError> {
+        let capacity = self.capacity();
+        if self.free_index >= capacity {
+            return Err(MerkleError::TreeFull { capacity });
+        }
+        let mut hash_val = *leaf;
+        let mut index = self.free_index;
+        self.free_index += 1;
+        for i in 1..self.height {
+            if index.is_multiple_of(2) {
+                self.left_path[i - 1] = hash_val;
+                hash_val = H::hash(&hash_val, &self.precomputed[i - 1]);
+            } else {
+                hash_val = H::hash(&self.left_path[i - 1], &hash_val);
+            }
+            index /= 2;
+        }
+        self.left_path[self.height - 1] = hash_val;
+        Ok(self.free_index - 1)
+    }
+
+    /// Number of leaves added so far.
+    pub fn len(&self) -> usize {
+        self.free_index
+    }
+
+    /// Returns `true` if no leaf has been added yet.
+    pub fn is_empty(&self) -> bool {
+        self.free_index == 0
+    }
+
+    /// Number of levels in the tree, including the leaf and root levels.
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Maximum number of leaves the tree can hold, `1 << (height - 1)`.
+    pub fn capacity(&self) -> usize {
+        1usize
+            .checked_shl((self.height - 1) as u32)
+            .unwrap_or(usize::MAX)
+    }
+
+    /// Returns the current tree root.
+    pub fn root(&self) -> Felt {
+        self.left_path[self.height - 1]
+    }
+
+    /// Returns the frontier, in the format of `HybridMerkleTree::frontier`.
+    pub fn frontier(&self) -> &[Felt] {
+        &self.left_path
+    }
+
+    /// Generates the proof for the most recently added leaf, in the same format as
+    /// `HybridMerkleTree::path`. Any other index fails with `PathUnavailable`.
+    pub fn path(&self, index: usize) -> Result<(Vec<Felt>, Vec<bool>), MerkleError> {
+        if index >= self.free_index {
+            return Err(MerkleError::LeafNotFound {
+                index,
+                len: self.free_index,
+            });
+        }
+        if index + 1 != self.free_index {
+            return Err(MerkleError::PathUnavailable { index });
+        }
+        let mut elements = Vec::with_capacity(self.height - 1);
+        let mut indices = Vec::with_capacity(self.height - 1);
+        let mut index = index;
+        for i in 0..(self.height - 1) {
+            let is_right = index % 2 == 1;
+            // Nothing has been added to the right of the latest leaf yet.
+            elements.push(if is_right {
+                self.left_path[i]
+            } else {
+                self.precomputed[i]
+            });
+            indices.push(is_right);
+            index /= 2;
+        }
+        Ok((elements, indices))
+    }
+}
+
+/// StreamingRootBuilder computes the root of leaves pushed in order, for datasets too large
+/// to hold for `HybridMerkleTree::from_leaves`. It keeps one pending left node per level and
+/// only hashes a node once both of its children are known, so `push` costs one hash on
+/// average, and `finalize` pads the incomplete right edge with the precomputed null hashes.
+#[derive(Debug, Clone)]
+pub struct StreamingRootBuilder<H: MerkleHasher = PoseidonHasher> {
+    height: usize,
+    precomputed: Vec<Felt>,
+    // pending[l] is the completed node at level l awaiting its right sibling, when bit l of
+    // `len` is set; the last level holds the root of a full tree.
+    pending: Vec<Felt>,
+    len: usize,
+    hasher: PhantomData<H>,
+}
+
+impl StreamingRootBuilder {
+    pub fn new(height: usize) -> Self {
+        Self::with_hasher(height)
+    }
+}
+
+impl<H: MerkleHasher> StreamingRootBuilder<H> {
+    /// Same as `new`, for a tree combining nodes with `H`.
+    /// Panics if `height` is 0.
+    pub fn with_hasher(height: usize) -> Self {
+        assert_height(height);
+        let precomputed = precomputed_hashes::<H>(height, default_null_base());
+        let pending = precomputed.clone();
+        Self {
+            height,
+            precomputed,
+            pending,
+            len: 0,
+            hasher: PhantomData,
+        }
+    }
+
+    /// Appends the next leaf and returns its index.
+    pub fn push(&mut self, leaf: &Felt) -> Result<usize, MerkleError> {
+        let capacity = 1usize
+            .checked_shl((self.height - 1) as u32)
+            .unwrap_or(usize::MAX);
+        if self.len >= capacity {
+            return Err(MerkleError::TreeFull { capacity });
+        }
+        let mut node = *leaf;
+        let mut level = 0;
+        while self.len.checked_shr(level as u32).unwrap_or(0) & 1 == 1 {
+            node = H::hash(&self.pending[level], &node);
+            level += 1;
+        }
+        self.pending[level] = node;
+        self.len += 1;
+        Ok(self.len - 1)
+    }
+
+    /// Number of leaves pushed so far.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if no leaf has been pushed yet.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the root of the pushed leaves, the same as `HybridMerkleTree::from_leaves`.
+    pub fn finalize(self) -> Felt {
+        // The running node on the right edge, once its level holds any leaf.
+        let mut node: Option<Felt> = None;
+        for level in 0..(self.height - 1) {
+            let has_left = self.len.checked_shr(level as u32).unwrap_or(0) & 1 == 1;
+            node = match (has_left, node) {
+                (true, Some(right)) => Some(H::hash(&self.pending[level], &right)),
+                (true, None) => Some(H::hash(&self.pending[level], &self.precomputed[level])),
+                (false, Some(left)) => Some(H::hash(&left, &self.precomputed[level])),
+                (false, None) => None,
+            };
+        }
+        match node {
+            Some(root) => root,
+            // Either no leaf was pushed, or the tree is full and its root is pending.
+            None if self.is_empty() => self.precomputed[self.height - 1],
+            None => self.pending[self.height - 1],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::merkle::{HybridMerkleTree, LeafIndex};
+
+    #[test]
+    fn test_frontier_matches_hybrid_tree() {
+        let mut frontier = FrontierMerkleTree::new(4);
+        let mut tree = HybridMerkleTree::new(4);
+        assert_eq!(frontier.root(), tree.root());
+        for i in 0..8 {
+            let leaf = Felt::from(i as u32 + 1);
+            assert_eq!(frontier.add_leaf(&leaf), tree.add_leaf(&leaf));
+            assert_eq!(frontier.root(), tree.root());
+            assert_eq!(frontier.path(i), tree.path(LeafIndex(i)));
+        }
+        assert_eq!(
+            frontier.add_leaf(&Felt::ZERO),
+            Err(MerkleError::TreeFull { capacity: 8 })
+        );
+    }
+
+    #[test]
+    fn test_streaming_root_matches_from_leaves() {
+        let leaves: Vec<Felt> = (0..1000u32).map(Felt::from).collect();
+        let mut builder = StreamingRootBuilder::new(11);
+        for (index, leaf) in leaves.iter().enumerate() {
+            assert_eq!(builder.push(leaf), Ok(index));
+        }
+        assert_eq!(
+            builder.finalize(),
+            HybridMerkleTree::from_leaves(11, &leaves).unwrap().root()
+        );
+
+        for count in 0..=4 {
+            let mut builder = StreamingRootBuilder::new(3);
+            for leaf in &leaves[..count] {
+                builder.push(leaf).unwrap();
+            }
+            if count == 4 {
+                assert_eq!(
+                    builder.clone().push(&Felt::ZERO),
+                    Err(MerkleError::TreeFull { capacity: 4 })
+                );
+            }
+            assert_eq!(
+                builder.finalize(),
+                HybridMerkleTree::from_leaves(3, &leaves[..count])
+                    .unwrap()
+                    .root()
+            );
+        }
+    }
+
+    #[test]
+    fn test_resume_from_exported_frontier() {
+        let mut tree = HybridMerkleTree::new(4);
+        tree.add_leaves(&[1u32, 2, 3, 4, 5].map(Felt::from))
+            .unwrap();
+        let mut frontier = FrontierMerkleTree::from_frontier(4, 5, tree.frontier()).unwrap();
+        assert_eq!(frontier.root(), tree.root());
+
+        let leaf = Felt::from(6);
+        assert_eq!(frontier.add_leaf(&leaf), tree.add_leaf(&leaf));
+        assert_eq!(frontier.root(), tree.root());
+        assert_eq!(frontier.path(5), tree.path(LeafIndex(5)));
+        assert_eq!(frontier.frontier(), tree.frontier());
+
+        assert_eq!(
+            FrontierMerkleTree::from_frontier(4, 5, &tree.frontier()[..3]).map(|_| ()),
+            Err(MerkleError::HeightMismatch {
+                expected: 4,
+                actual: 3
+            })
+        );
+        assert_eq!(
+            FrontierMerkleTree::from_frontier(4, 9, tree.frontier()).map(|_| ()),
+            Err(MerkleError::TreeFull { capacity: 8 })
+        );
+    }
+
+    #[test]
+    fn test_resume_with_custom_null_base() {
+        let mut tree = HybridMerkleTree::with_null_base(4, Felt::ZERO);
+        tree.add_leaves(&[1u32, 2, 3].map(Felt::from)).unwrap();
+        let mut frontier =
+            FrontierMerkleTree::from_frontier_with_null_base(4, 3, tree.frontier(), Felt::ZERO)
+                .unwrap();
+        let leaf = Felt::from(4);
+        frontier.add_leaf(&leaf).unwrap();
+        tree.add_leaf(&leaf).unwrap();
+        assert_eq!(frontier.root(), tree.root());
+
+        // The default base pads the new leaf's sibling with the wrong null hash.
+        let mut wrong = FrontierMerkleTree::from_frontier(4, tree.len(), tree.frontier()).unwrap();
+        wrong.add_leaf(&Felt::from(5)).unwrap();
+        tree.add_leaf(&Felt::from(5)).unwrap();
+        assert_ne!(wrong.root(), tree.root());
+    }
+
+    #[test]
+    fn test_frontier_rejects_historical_paths() {
+        let mut frontier = FrontierMerkleTree::new(3);
+        frontier.add_leaf(&Felt::from(1)).unwrap();
+        frontier.add_leaf(&Felt::from(2)).unwrap();
+        assert_eq!(
+            frontier.path(0),
+            Err(MerkleError::PathUnavailable { index: 0 })
+        );
+        assert_eq!(
+            frontier.path(2),
+            Err(MerkleError::LeafNotFound { index: 2, len: 2 })
+        );
+    }
+}
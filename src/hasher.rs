@@ -0,0 +1,62 @@
+use starknet_types_core::{
+    felt::Felt,
+    hash::{Pedersen, Poseidon, StarkHash},
+};
+
+#[cfg(feature = "std")]
+use std::sync::OnceLock;
+
+use crate::helpers::NULL_HASHES;
+#[cfg(feature = "std")]
+use crate::helpers::{default_null_base, hash_null_levels};
+
+/// Levels of default-base null hashes `PoseidonHasher` computes once and shares between trees,
+/// enough for a `SparseMerkleTree`. It is also the tallest tree deserialization accepts.
+pub const CACHED_NULL_HASHES_HEIGHT: usize = 256;
+
+/// Two-to-one hash used to combine sibling nodes.
+pub trait MerkleHasher {
+    /// Hashes a left node `a` with a right node `b`.
+    fn hash(a: &Felt, b: &Felt) -> Felt;
+
+    /// Null hashes of the default null base, level by level, if known ahead of time.
+    /// `precomputed_hashes` uses them instead of hashing when the tree is short enough.
+    const NULL_HASHES: &'static [[u8; 32]] = &[];
+
+    /// Null hashes of the default null base, level by level, computed once per process and
+    /// shared by every tree. `precomputed_hashes` prefers them over `NULL_HASHES`.
+    fn cached_null_hashes() -> Option<&'static [Felt]> {
+        None
+    }
+}
+
+/// Poseidon hasher, the default used by the Noir circuit.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PoseidonHasher;
+
+impl MerkleHasher for PoseidonHasher {
+    const NULL_HASHES: &'static [[u8; 32]] = &NULL_HASHES;
+
+    fn hash(a: &Felt, b: &Felt) -> Felt {
+        Poseidon::hash(a, b)
+    }
+
+    #[cfg(feature = "std")]
+    fn cached_null_hashes() -> Option<&'static [Felt]> {
+        static CACHE: OnceLock<Vec<Felt>> = OnceLock::new();
+        let cached = CACHE.get_or_init(|| {
+            hash_null_levels::<Self>(CACHED_NULL_HASHES_HEIGHT, default_null_base())
+        });
+        Some(cached)
+    }
+}
+
+/// Pedersen hasher, matching Merkle trees of pre-Poseidon Starknet contracts.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PedersenHasher;
+
+impl MerkleHasher for PedersenHasher {
+    fn hash(a: &Felt, b: &Felt) -> Felt {
+        Pedersen::hash(a, b)
+    }
+}
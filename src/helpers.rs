@@ -1,36 +1,528 @@
+use alloc::collections::BTreeMap;
+use alloc::format;
+use alloc::string::ToString;
+use alloc::vec::Vec;
+
 use starknet_types_core::{
     felt::Felt,
     hash::{Poseidon, StarkHash},
 };
 
-/// Generates a vector of precomputed "null" hashes as Felt values for the given tree height.
-/// Here we use a custom base value (which can be set to Felt::ZERO if desired).
-pub fn precomputed_hashes(height: usize) -> Vec<Felt> {
-    let mut hashes = Vec::with_capacity(height);
-    let base_bytes: [u8; 32] = [
+use crate::error::MerkleError;
+use crate::hasher::MerkleHasher;
+use crate::merkle::{CompressedProof, MultiProof, RangeProof};
+
+/// Big-endian bytes of the default null base used by `HybridMerkleTree::new`.
+pub const DEFAULT_NULL_BASE_BYTES: [u8; 32] = [
+    2, 147, 211, 232, 168, 15, 64, 13, 170, 175, 253, 213, 147, 46, 43, 204, 136, 20, 186, 184,
+    244, 20, 167, 93, 202, 207, 135, 49, 143, 139, 20, 197,
+];
+
+/// Big-endian null hashes of a Poseidon tree over the default null base, as printed by the
+/// `noir-merkle-tree` binary: level 0 is the base and each level hashes two copies of the one
+/// below. `precomputed_hashes` slices this table instead of hashing for trees up to 13 levels.
+pub const NULL_HASHES: [[u8; 32]; 13] = [
+    [
         2, 147, 211, 232, 168, 15, 64, 13, 170, 175, 253, 213, 147, 46, 43, 204, 136, 20, 186, 184,
         244, 20, 167, 93, 202, 207, 135, 49, 143, 139, 20, 197,
-    ];
-    let base = Felt::from_bytes_be(&base_bytes);
-    hashes.push(base.clone());
+    ],
+    [
+        2, 150, 236, 72, 57, 103, 173, 63, 190, 52, 7, 35, 61, 179, 120, 182, 40, 76, 193, 252,
+        199, 141, 98, 69, 123, 151, 164, 190, 103, 68, 173, 13,
+    ],
+    [
+        4, 18, 123, 232, 59, 66, 41, 111, 226, 143, 152, 248, 253, 218, 41, 185, 110, 34, 229, 217,
+        5, 1, 247, 211, 27, 132, 231, 41, 236, 47, 172, 63,
+    ],
+    [
+        3, 56, 131, 48, 90, 176, 223, 26, 183, 97, 1, 83, 87, 138, 77, 81, 11, 132, 88, 65, 184,
+        77, 144, 237, 153, 49, 51, 206, 76, 232, 248, 39,
+    ],
+    [
+        4, 14, 64, 147, 254, 90, 247, 59, 236, 246, 80, 127, 71, 90, 82, 154, 120, 228, 159, 96,
+        69, 57, 234, 95, 53, 71, 5, 155, 94, 127, 16, 118,
+    ],
+    [
+        5, 93, 172, 116, 55, 82, 122, 137, 182, 192, 62, 203, 113, 65, 25, 62, 48, 163, 143, 135,
+        50, 79, 61, 162, 47, 59, 140, 231, 65, 26, 136, 205,
+    ],
+    [
+        1, 236, 133, 154, 25, 202, 154, 184, 216, 102, 62, 184, 90, 9, 207, 185, 2, 50, 111, 193,
+        75, 58, 33, 33, 86, 158, 210, 132, 122, 156, 34, 191,
+    ],
+    [
+        7, 101, 225, 55, 205, 166, 104, 88, 48, 207, 20, 236, 82, 152, 244, 96, 151, 231, 138, 59,
+        224, 106, 161, 91, 236, 237, 144, 127, 26, 34, 217, 253,
+    ],
+    [
+        5, 210, 93, 107, 143, 17, 227, 69, 66, 204, 133, 4, 7, 137, 153, 38, 189, 97, 226, 83, 221,
+        119, 100, 119, 153, 97, 81, 246, 85, 79, 61, 161,
+    ],
+    [
+        4, 162, 19, 88, 195, 231, 84, 118, 98, 22, 180, 201, 62, 207, 174, 34, 46, 134, 130, 47,
+        116, 110, 112, 110, 86, 63, 58, 5, 239, 57, 137, 89,
+    ],
+    [
+        7, 84, 239, 66, 179, 227, 183, 77, 250, 114, 180, 211, 161, 210, 9, 228, 43, 177, 202, 151,
+        255, 44, 136, 255, 24, 85, 52, 95, 91, 53, 126, 72,
+    ],
+    [
+        2, 188, 177, 54, 170, 203, 219, 36, 176, 74, 241, 228, 187, 11, 63, 251, 180, 152, 251, 78,
+        24, 238, 208, 169, 234, 109, 103, 209, 227, 100, 72, 59,
+    ],
+    [
+        5, 33, 112, 145, 223, 236, 99, 240, 81, 51, 81, 160, 8, 32, 137, 111, 210, 234, 202, 101,
+        105, 8, 72, 55, 60, 249, 194, 132, 4, 128, 238, 127,
+    ],
+];
+
+/// Returns the default null base as a Felt.
+pub fn default_null_base() -> Felt {
+    Felt::from_bytes_be(&DEFAULT_NULL_BASE_BYTES)
+}
+
+/// Byte order of a Felt's 32-byte encoding. Starknet tooling uses big-endian, while some
+/// provers and hash libraries expect little-endian.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Endianness {
+    /// Most significant byte first, as `Felt::to_bytes_be`.
+    #[default]
+    Big,
+    /// Least significant byte first, as `Felt::to_bytes_le`.
+    Little,
+}
+
+/// Encodes `felt` as 32 bytes in `endianness` order.
+pub fn felt_to_bytes(felt: &Felt, endianness: Endianness) -> [u8; 32] {
+    match endianness {
+        Endianness::Big => felt.to_bytes_be(),
+        Endianness::Little => felt.to_bytes_le(),
+    }
+}
+
+/// Decodes 32 bytes written by `felt_to_bytes` with the same `endianness`. Values above the
+/// modulus are reduced, as by `Felt::from_bytes_be`.
+pub fn felt_from_bytes(bytes: &[u8; 32], endianness: Endianness) -> Felt {
+    match endianness {
+        Endianness::Big => Felt::from_bytes_be(bytes),
+        Endianness::Little => Felt::from_bytes_le(bytes),
+    }
+}
+
+/// Ensures a tree of `height` has at least one level, the leaf level.
+/// A height-1 tree holds a single leaf, which is also its root.
+pub fn check_height(height: usize) -> Result<(), MerkleError> {
+    if height == 0 {
+        return Err(MerkleError::InvalidHeight { height });
+    }
+    Ok(())
+}
+
+/// Smallest height whose tree holds `leaves` leaves, `ceil(log2(leaves)) + 1`. A height-1
+/// tree is returned for no leaf at all.
+pub fn height_for(leaves: usize) -> usize {
+    match leaves {
+        0 | 1 => 1,
+        n => (usize::BITS - (n - 1).leading_zeros()) as usize + 1,
+    }
+}
+
+/// Size and verification cost of the inclusion proof of one leaf, as returned by `proof_cost`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProofCost {
+    /// Siblings in the proof, one per level below the root.
+    pub siblings: usize,
+    /// Hashes a verifier computes to rebuild the root from the leaf.
+    pub poseidon_calls: usize,
+    /// Bytes taken by the siblings, 32 each.
+    pub bytes: usize,
+}
+
+/// Returns the size and verification cost of a proof in a tree of `height`, without building
+/// one. A height-1 tree, whose leaf is the root, has empty proofs.
+pub fn proof_cost(height: usize) -> ProofCost {
+    let siblings = height.saturating_sub(1);
+    ProofCost {
+        siblings,
+        poseidon_calls: siblings,
+        bytes: siblings * 32,
+    }
+}
+
+/// Panicking version of `check_height`, for constructors that cannot fail otherwise.
+pub(crate) fn assert_height(height: usize) {
+    if let Err(e) = check_height(height) {
+        panic!("{}", e);
+    }
+}
+
+/// Generates a vector of precomputed "null" hashes as Felt values for the given tree height.
+/// `base` is the empty-leaf value (e.g. `default_null_base()` or `Felt::ZERO`).
+/// The default base is served from `H::cached_null_hashes` or `H::NULL_HASHES` when they are
+/// tall enough, so only other bases hash on every call.
+pub fn precomputed_hashes<H: MerkleHasher>(height: usize, base: Felt) -> Vec<Felt> {
+    if base == default_null_base() {
+        if let Some(cached) = H::cached_null_hashes().filter(|cached| height <= cached.len()) {
+            return cached[..height].to_vec();
+        }
+        if height <= H::NULL_HASHES.len() {
+            return H::NULL_HASHES[..height]
+                .iter()
+                .map(Felt::from_bytes_be)
+                .collect();
+        }
+    }
+    hash_null_levels::<H>(height, base)
+}
+
+/// Hashes the `height` null levels over `base`, without looking up any cached table.
+pub(crate) fn hash_null_levels<H: MerkleHasher>(height: usize, base: Felt) -> Vec<Felt> {
+    let mut hashes = Vec::with_capacity(height);
+    hashes.push(base);
     let mut current = base;
     for _ in 1..height {
-        current = Poseidon::hash(&current, &current);
-        hashes.push(current.clone());
+        current = H::hash(&current, &current);
+        hashes.push(current);
     }
     hashes
 }
 
 /// Simulates the Noir circuit function to compute the Merkle root from a leaf and its proof.
-pub fn compute_merkle_root_rust(leaf: Felt, mut index: u32, hash_path: &[Felt]) -> Felt {
+pub fn compute_merkle_root_rust<H: MerkleHasher>(
+    leaf: Felt,
+    mut index: usize,
+    hash_path: &[Felt],
+) -> Felt {
     let mut current = leaf;
     for sibling in hash_path {
-        if index % 2 == 1 {
-            current = Poseidon::hash(sibling, &current);
+        if !index.is_multiple_of(2) {
+            current = H::hash(sibling, &current);
         } else {
-            current = Poseidon::hash(&current, sibling);
+            current = H::hash(&current, sibling);
         }
         index /= 2;
     }
     current
 }
+
+/// Hashes `a` and `b` with the numerically smaller one first, for `SiblingOrder::Sorted`.
+pub fn hash_sorted<H: MerkleHasher>(a: &Felt, b: &Felt) -> Felt {
+    if a <= b {
+        H::hash(a, b)
+    } else {
+        H::hash(b, a)
+    }
+}
+
+/// Same as `compute_merkle_root_rust`, for a tree built with `SiblingOrder::Sorted`: each
+/// level hashes the smaller of the running hash and its sibling first, so no index is needed.
+pub fn compute_merkle_root_sorted<H: MerkleHasher>(leaf: Felt, hash_path: &[Felt]) -> Felt {
+    hash_path
+        .iter()
+        .fold(leaf, |current, sibling| hash_sorted::<H>(&current, sibling))
+}
+
+/// Same as `compute_merkle_root_rust`, but takes the side indicators returned by `path`
+/// instead of the index: `bits[i]` is `true` when the running hash is the right child at
+/// level `i`. Only as many levels as both `bits` and `siblings` cover are hashed.
+pub fn compute_root_from_bits<H: MerkleHasher>(
+    leaf: &Felt,
+    bits: &[bool],
+    siblings: &[Felt],
+) -> Felt {
+    bits.iter()
+        .zip(siblings)
+        .fold(*leaf, |current, (&is_right, sibling)| {
+            if is_right {
+                H::hash(sibling, &current)
+            } else {
+                H::hash(&current, sibling)
+            }
+        })
+}
+
+/// Folds the side indicators returned by `path` (leaf level first) back into the leaf index.
+pub fn index_from_bits(bits: &[bool]) -> usize {
+    bits.iter()
+        .rev()
+        .fold(0, |index, &is_right| (index << 1) | is_right as usize)
+}
+
+/// Side indicators of leaf `index` in a tree of `height`, as returned by `path`.
+pub fn bits_from_index(index: usize, height: usize) -> Vec<bool> {
+    (0..height.saturating_sub(1))
+        .map(|level| index.checked_shr(level as u32).unwrap_or(0) & 1 == 1)
+        .collect()
+}
+
+/// Bytes of leaf data packed into each field element by `hash_leaf_data`.
+pub const LEAF_DATA_CHUNK_BYTES: usize = 31;
+
+/// Hashes arbitrary leaf data into a Felt, as done by `HybridMerkleTree::add_leaf_data`.
+/// The data is split into 31-byte chunks, each read as a big-endian integer (the last chunk
+/// may be shorter), and the result is `Poseidon::hash_array([len, chunk_0, chunk_1, ..])`
+/// where `len` is the data length in bytes, so that trailing zero bytes are not ambiguous.
+pub fn hash_leaf_data(bytes: &[u8]) -> Felt {
+    let mut elements = Vec::with_capacity(1 + bytes.len().div_ceil(LEAF_DATA_CHUNK_BYTES));
+    elements.push(Felt::from(bytes.len()));
+    for chunk in bytes.chunks(LEAF_DATA_CHUNK_BYTES) {
+        let mut buf = [0u8; 32];
+        buf[32 - chunk.len()..].copy_from_slice(chunk);
+        elements.push(Felt::from_bytes_be(&buf));
+    }
+    Poseidon::hash_array(&elements)
+}
+
+/// Parses a leaf value written as a `0x`-prefixed hex or a decimal integer. Unlike
+/// `Felt::from_hex` and `Felt::from_dec_str`, values that are not below the field modulus
+/// are rejected with `InvalidFelt` instead of being silently reduced.
+pub fn parse_felt(s: &str) -> Result<Felt, MerkleError> {
+    let invalid = || MerkleError::InvalidFelt {
+        value: s.to_string(),
+    };
+    let (digits, felt, canonical) = match s.strip_prefix("0x") {
+        Some(hex) => {
+            let felt = Felt::from_hex(s).map_err(|_| invalid())?;
+            (hex, felt, format!("{:x}", felt))
+        }
+        None => {
+            let felt = Felt::from_dec_str(s).map_err(|_| invalid())?;
+            (s, felt, felt.to_string())
+        }
+    };
+    // A reduced value no longer prints as the digits it was parsed from.
+    let digits = match digits.trim_start_matches('0') {
+        "" if !digits.is_empty() => "0",
+        trimmed => trimmed,
+    };
+    if !digits.eq_ignore_ascii_case(&canonical) {
+        return Err(invalid());
+    }
+    Ok(felt)
+}
+
+/// Pre-hashes `leaf` with a domain tag, `H(domain, leaf)`, as done for trees configured
+/// with `HybridMerkleTree::with_leaf_domain`. Without a domain the leaf is returned as is.
+pub fn tag_leaf<H: MerkleHasher>(leaf: &Felt, domain: Option<&Felt>) -> Felt {
+    match domain {
+        Some(domain) => H::hash(domain, leaf),
+        None => *leaf,
+    }
+}
+
+/// Same as `compute_merkle_root_rust`, but first tags the raw `leaf` with `domain`.
+pub fn compute_merkle_root_with_domain<H: MerkleHasher>(
+    leaf: Felt,
+    domain: Option<&Felt>,
+    index: usize,
+    hash_path: &[Felt],
+) -> Felt {
+    compute_merkle_root_rust::<H>(tag_leaf::<H>(&leaf, domain), index, hash_path)
+}
+
+/// Verifies that `proof` links `leaf` at position `index` to the root of a tree of `height`
+/// whose nodes are combined with `H`.
+/// Fails with `ProofLengthMismatch` unless the proof has exactly `height - 1` siblings, and
+/// with `IndexOutOfRange` if `index` does not fit in such a tree.
+pub fn verify_proof<H: MerkleHasher>(
+    root: &Felt,
+    leaf: &Felt,
+    index: usize,
+    proof: &[Felt],
+    height: usize,
+) -> Result<bool, MerkleError> {
+    let expected = height.saturating_sub(1);
+    if proof.len() != expected {
+        return Err(MerkleError::ProofLengthMismatch {
+            expected,
+            actual: proof.len(),
+        });
+    }
+    if index.checked_shr(expected as u32).unwrap_or(0) != 0 {
+        return Err(MerkleError::IndexOutOfRange {
+            index,
+            capacity: 1usize.checked_shl(expected as u32).unwrap_or(usize::MAX),
+        });
+    }
+    if proof.is_empty() {
+        return Ok(leaf == root);
+    }
+    Ok(compute_merkle_root_rust::<H>(*leaf, index, proof) == *root)
+}
+
+/// Recomputes the root `proof` leads to from `leaf` at `index`, and returns whether it is
+/// `root` along with the recomputed root, so a failing proof can be diffed against `root`.
+/// Unlike `verify_proof`, the proof length is not checked.
+pub fn compute_and_verify<H: MerkleHasher>(
+    root: &Felt,
+    leaf: &Felt,
+    index: usize,
+    proof: &[Felt],
+) -> (bool, Felt) {
+    let computed = compute_merkle_root_rust::<H>(*leaf, index, proof);
+    (computed == *root, computed)
+}
+
+/// Same as `verify_proof` for a `CompressedProof`, whose omitted siblings are refilled with
+/// the `precomputed_hashes` of the default null base. Proofs from trees with another null
+/// base only verify with `verify_compressed_with_null_base`.
+pub fn verify_compressed<H: MerkleHasher>(
+    root: &Felt,
+    leaf: &Felt,
+    index: usize,
+    proof: &CompressedProof,
+    height: usize,
+) -> Result<bool, MerkleError> {
+    verify_compressed_with_null_base::<H>(root, leaf, index, proof, height, default_null_base())
+}
+
+/// Same as `verify_compressed`, refilling the omitted siblings from the null base `base`.
+pub fn verify_compressed_with_null_base<H: MerkleHasher>(
+    root: &Felt,
+    leaf: &Felt,
+    index: usize,
+    proof: &CompressedProof,
+    height: usize,
+    base: Felt,
+) -> Result<bool, MerkleError> {
+    let nulls = precomputed_hashes::<H>(proof.siblings.len(), base);
+    let siblings: Vec<Felt> = proof
+        .siblings
+        .iter()
+        .zip(nulls)
+        .map(|(sibling, null)| sibling.unwrap_or(null))
+        .collect();
+    verify_proof::<H>(root, leaf, index, &siblings, height)
+}
+
+/// Verifies a `consistency_proof`: that the tree whose root is `new_root` at `new_len` leaves
+/// keeps the first `old_len` leaves of the tree whose root was `old_root`. The height is the
+/// proof length, and empty subtrees hash from the default null base.
+pub fn verify_consistency<H: MerkleHasher>(
+    old_root: &Felt,
+    old_len: usize,
+    new_root: &Felt,
+    new_len: usize,
+    proof: &[Felt],
+) -> bool {
+    let height = proof.len();
+    let capacity = 1usize
+        .checked_shl(height.saturating_sub(1) as u32)
+        .unwrap_or(usize::MAX);
+    if height == 0 || old_len == 0 || old_len > new_len || new_len > capacity {
+        return false;
+    }
+    let nulls = precomputed_hashes::<H>(height, default_null_base());
+    let last = old_len - 1;
+    let mut old_node = proof[0];
+    let mut new_node = proof[0];
+    for (level, sibling) in proof[1..].iter().enumerate() {
+        let index = last.checked_shr(level as u32).unwrap_or(0);
+        if index % 2 == 1 {
+            old_node = H::hash(sibling, &old_node);
+            new_node = H::hash(sibling, &new_node);
+        } else {
+            // The right sibling was empty at `old_len` leaves, and still is if it starts at
+            // or after `new_len`.
+            let first_leaf = (index + 1).checked_shl(level as u32).unwrap_or(usize::MAX);
+            if first_leaf >= new_len && *sibling != nulls[level] {
+                return false;
+            }
+            old_node = H::hash(&old_node, &nulls[level]);
+            new_node = H::hash(&new_node, sibling);
+        }
+    }
+    old_node == *old_root && new_node == *new_root
+}
+
+/// Verifies independent `(leaf, index, proof)` items against the same `root` of a tree of
+/// `height`, returning one flag per item. Items whose proof is malformed are reported as `false`.
+/// Items are checked in parallel when the `rayon` feature is enabled.
+pub fn verify_batch<H: MerkleHasher>(
+    root: &Felt,
+    items: &[(Felt, usize, Vec<Felt>)],
+    height: usize,
+) -> Vec<bool> {
+    let verify = |(leaf, index, proof): &(Felt, usize, Vec<Felt>)| {
+        verify_proof::<H>(root, leaf, *index, proof, height).unwrap_or(false)
+    };
+    #[cfg(feature = "rayon")]
+    {
+        use rayon::prelude::*;
+
+        items.par_iter().map(verify).collect()
+    }
+    #[cfg(not(feature = "rayon"))]
+    {
+        items.iter().map(verify).collect()
+    }
+}
+
+/// Verifies a `MultiProof` for `leaves`, where `leaves[i]` sits at `indices[i]`.
+/// Returns `false` if the proof does not hold exactly the siblings the indices require.
+pub fn verify_multi_proof<H: MerkleHasher>(
+    root: &Felt,
+    leaves: &[Felt],
+    indices: &[usize],
+    proof: &MultiProof,
+) -> bool {
+    verify_known_leaves::<H>(root, leaves, indices, proof.height, &proof.siblings)
+}
+
+/// Verifies a `RangeProof` for `leaves`, the contiguous leaves starting at index `start`.
+pub fn verify_range_proof<H: MerkleHasher>(
+    root: &Felt,
+    leaves: &[Felt],
+    start: usize,
+    proof: &RangeProof,
+) -> bool {
+    let indices: Vec<usize> = (start..start.saturating_add(leaves.len())).collect();
+    verify_known_leaves::<H>(root, leaves, &indices, proof.height, &proof.siblings)
+}
+
+/// Hashes `leaves` at `indices` up to the root of a tree of `height`, taking each sibling that
+/// cannot be recomputed from `siblings` in order, and checks that all of them were used.
+fn verify_known_leaves<H: MerkleHasher>(
+    root: &Felt,
+    leaves: &[Felt],
+    indices: &[usize],
+    height: usize,
+    siblings: &[Felt],
+) -> bool {
+    if leaves.is_empty() || leaves.len() != indices.len() || height == 0 {
+        return false;
+    }
+    let mut known = BTreeMap::new();
+    for (&index, &leaf) in indices.iter().zip(leaves) {
+        if known
+            .insert(index, leaf)
+            .is_some_and(|previous| previous != leaf)
+        {
+            return false;
+        }
+    }
+    let mut siblings = siblings.iter();
+    for _ in 0..(height - 1) {
+        let mut parents = BTreeMap::new();
+        for (&index, node) in &known {
+            if parents.contains_key(&(index / 2)) {
+                continue;
+            }
+            let sibling = match known.get(&(index ^ 1)) {
+                Some(sibling) => sibling,
+                None => match siblings.next() {
+                    Some(sibling) => sibling,
+                    None => return false,
+                },
+            };
+            let parent = if index % 2 == 1 {
+                H::hash(sibling, node)
+            } else {
+                H::hash(node, sibling)
+            };
+            parents.insert(index / 2, parent);
+        }
+        known = parents;
+    }
+    siblings.next().is_none() && known.len() == 1 && known.get(&0) == Some(root)
+}
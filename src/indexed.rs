@@ -0,0 +1,189 @@
+use alloc::collections::BTreeMap;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use starknet_types_core::{
+    felt::Felt,
+    hash::{Poseidon, StarkHash},
+};
+
+use crate::error::MerkleError;
+use crate::merkle::{HybridMerkleTree, LeafIndex, MerkleProof};
+
+/// Leaf of an indexed tree: a value and a link to the next larger value in the tree.
+/// `next_index == 0` marks the largest value, whose `next_value` is zero.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IndexedLeaf {
+    pub value: Felt,
+    pub next_index: usize,
+    pub next_value: Felt,
+}
+
+impl IndexedLeaf {
+    /// Hash committed in the underlying tree, `Poseidon(value, next_index, next_value)`.
+    pub fn hash(&self) -> Felt {
+        Poseidon::hash_array(&[self.value, Felt::from(self.next_index), self.next_value])
+    }
+
+    /// Returns `true` if `value` falls strictly between this leaf and the next one.
+    pub fn covers(&self, value: &Felt) -> bool {
+        self.value < *value && (self.next_index == 0 || *value < self.next_value)
+    }
+}
+
+/// Proof that a value is absent: the low leaf whose gap contains it, and its inclusion proof.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NonMembershipProof {
+    pub low_leaf: IndexedLeaf,
+    pub proof: MerkleProof,
+}
+
+impl NonMembershipProof {
+    /// Checks that the low leaf is committed under `root` and that `value` lies in its gap.
+    pub fn verify(&self, root: &Felt, value: &Felt) -> bool {
+        self.low_leaf.covers(value)
+            && self.proof.leaf == self.low_leaf.hash()
+            && self.proof.verify(root)
+    }
+}
+
+/// IndexedMerkleTree keeps its leaves as a linked list sorted by value, so that the absence
+/// of a value can be proven by the leaf that skips over it, as in Aztec nullifier trees.
+/// Leaf 0 is the zero leaf, which links to the smallest inserted value.
+#[derive(Debug, Clone)]
+pub struct IndexedMerkleTree {
+    tree: HybridMerkleTree,
+    leaves: Vec<IndexedLeaf>,
+    sorted: BTreeMap<Felt, usize>, // Value to leaf index.
+}
+
+impl IndexedMerkleTree {
+    pub fn new(height: usize) -> Self {
+        let zero = IndexedLeaf {
+            value: Felt::ZERO,
+            next_index: 0,
+            next_value: Felt::ZERO,
+        };
+        let mut tree = HybridMerkleTree::new(height);
+        tree.add_leaf(&zero.hash())
+            .expect("A tree of height 1 or more holds the zero leaf");
+        Self {
+            tree,
+            leaves: vec![zero],
+            sorted: BTreeMap::from([(Felt::ZERO, 0)]),
+        }
+    }
+
+    /// Number of leaves, including the zero leaf.
+    pub fn len(&self) -> usize {
+        self.leaves.len()
+    }
+
+    /// Always `false`: the zero leaf is present from construction.
+    pub fn is_empty(&self) -> bool {
+        self.leaves.is_empty()
+    }
+
+    /// Returns the leaf at `index`, if one has been inserted.
+    pub fn get_leaf(&self, index: usize) -> Option<IndexedLeaf> {
+        self.leaves.get(index).copied()
+    }
+
+    /// Returns the current tree root.
+    pub fn root(&self) -> Felt {
+        self.tree.root()
+    }
+
+    /// Index of the leaf holding the largest value below `value`.
+    fn low_index(&self, value: &Felt) -> usize {
+        self.sorted
+            .range(..*value)
+            .next_back()
+            .map(|(_, &index)| index)
+            .unwrap_or(0)
+    }
+
+    /// Inserts `value`, relinking its low leaf to it. Returns the index of the new leaf.
+    pub fn insert(&mut self, value: &Felt) -> Result<usize, MerkleError> {
+        if let Some(&index) = self.sorted.get(value) {
+            return Err(MerkleError::ValueAlreadyPresent { index });
+        }
+        let capacity = self.tree.capacity();
+        let index = self.len();
+        if index >= capacity {
+            return Err(MerkleError::TreeFull { capacity });
+        }
+
+        let low_index = self.low_index(value);
+        let low = &mut self.leaves[low_index];
+        let leaf = IndexedLeaf {
+            value: *value,
+            next_index: low.next_index,
+            next_value: low.next_value,
+        };
+        low.next_index = index;
+        low.next_value = *value;
+        let low_hash = low.hash();
+
+        self.tree.update_leaf(LeafIndex(low_index), &low_hash)?;
+        self.tree.add_leaf(&leaf.hash())?;
+        self.leaves.push(leaf);
+        self.sorted.insert(*value, index);
+        Ok(index)
+    }
+
+    /// Proves that `value` is not in the tree.
+    pub fn non_membership_proof(&self, value: &Felt) -> Result<NonMembershipProof, MerkleError> {
+        if let Some(&index) = self.sorted.get(value) {
+            return Err(MerkleError::ValueAlreadyPresent { index });
+        }
+        let low_index = self.low_index(value);
+        Ok(NonMembershipProof {
+            low_leaf: self.leaves[low_index],
+            proof: self.tree.proof(LeafIndex(low_index))?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_non_membership_of_gap_value() {
+        let mut tree = IndexedMerkleTree::new(4);
+        assert_eq!(tree.insert(&Felt::from(30u32)), Ok(1));
+        assert_eq!(tree.insert(&Felt::from(10u32)), Ok(2));
+        assert_eq!(tree.insert(&Felt::from(50u32)), Ok(3));
+
+        let low = tree.get_leaf(2).unwrap();
+        assert_eq!(low.next_index, 1);
+        assert_eq!(low.next_value, Felt::from(30u32));
+
+        let gap = Felt::from(20u32);
+        let proof = tree.non_membership_proof(&gap).unwrap();
+        assert_eq!(proof.low_leaf, low);
+        assert!(proof.verify(&tree.root(), &gap));
+        assert!(!proof.verify(&tree.root(), &Felt::from(40u32)));
+
+        let above = Felt::from(60u32);
+        let proof = tree.non_membership_proof(&above).unwrap();
+        assert_eq!(proof.low_leaf.next_index, 0);
+        assert!(proof.verify(&tree.root(), &above));
+    }
+
+    #[test]
+    fn test_insert_rejects_present_value() {
+        let mut tree = IndexedMerkleTree::new(3);
+        let value = Felt::from(7u32);
+        tree.insert(&value).unwrap();
+        assert_eq!(
+            tree.insert(&value),
+            Err(MerkleError::ValueAlreadyPresent { index: 1 })
+        );
+        assert_eq!(
+            tree.non_membership_proof(&value),
+            Err(MerkleError::ValueAlreadyPresent { index: 1 })
+        );
+    }
+}
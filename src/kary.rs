@@ -0,0 +1,220 @@
+use alloc::vec;
+use alloc::vec::Vec;
+
+use starknet_types_core::{
+    felt::Felt,
+    hash::{Poseidon, StarkHash},
+};
+
+use crate::error::MerkleError;
+use crate::helpers::{assert_height, default_null_base};
+
+/// Hashes the `K` children of a node. Binary nodes use the two-to-one `Poseidon::hash`,
+/// so `KaryMerkleTree<2>` matches `HybridMerkleTree`; wider nodes use `Poseidon::hash_array`.
+pub fn hash_children(children: &[Felt]) -> Felt {
+    match children {
+        [left, right] => Poseidon::hash(left, right),
+        _ => Poseidon::hash_array(children),
+    }
+}
+
+/// Generates the null hash of each level for a `K`-ary tree: `base` for the leaves, then
+/// the hash of `K` copies of the level below.
+pub fn precomputed_kary_hashes<const K: usize>(height: usize, base: Felt) -> Vec<Felt> {
+    let mut hashes = Vec::with_capacity(height);
+    hashes.push(base);
+    let mut current = base;
+    for _ in 1..height {
+        current = hash_children(&[current; K]);
+        hashes.push(current);
+    }
+    hashes
+}
+
+/// Recomputes a `K`-ary root from a leaf, its index and the `K - 1` siblings of each level,
+/// listed in child order with the running node left out. Fails with `MalformedProof` at the
+/// first level that does not hold exactly `K - 1` siblings.
+pub fn compute_kary_root<const K: usize>(
+    leaf: Felt,
+    mut index: usize,
+    path: &[Vec<Felt>],
+) -> Result<Felt, MerkleError> {
+    let mut current = leaf;
+    for (level, siblings) in path.iter().enumerate() {
+        if siblings.len() != K - 1 {
+            return Err(MerkleError::MalformedProof { level });
+        }
+        let position = index % K;
+        let mut children = Vec::with_capacity(K);
+        children.extend_from_slice(&siblings[..position]);
+        children.push(current);
+        children.extend_from_slice(&siblings[position..]);
+        current = hash_children(&children);
+        index /= K;
+    }
+    Ok(current)
+}
+
+/// KaryMerkleTree is an append-only Merkle tree whose nodes have `K` children each, so a
+/// tree of the same capacity is shallower and its proofs have fewer levels.
+#[derive(Debug, Clone)]
+pub struct KaryMerkleTree<const K: usize> {
+    height: usize,
+    precomputed: Vec<Felt>,
+    layers: Vec<Vec<Felt>>, // Each layer stores computed hashes.
+}
+
+impl<const K: usize> KaryMerkleTree<K> {
+    pub fn new(height: usize) -> Self {
+        Self::with_null_base(height, default_null_base())
+    }
+
+    /// Creates an empty tree whose empty leaves hash from `base` instead of the default.
+    pub fn with_null_base(height: usize, base: Felt) -> Self {
+        assert!(K >= 2, "A k-ary tree needs at least two children per node");
+        assert_height(height);
+        Self {
+            height,
+            precomputed: precomputed_kary_hashes::<K>(height, base),
+            layers: vec![Vec::new(); height],
+        }
+    }
+
+    /// Number of leaves added so far.
+    pub fn len(&self) -> usize {
+        self.layers[0].len()
+    }
+
+    /// Returns `true` if no leaf has been added yet.
+    pub fn is_empty(&self) -> bool {
+        self.layers[0].is_empty()
+    }
+
+    /// Maximum number of leaves the tree can hold, `K^(height - 1)`.
+    pub fn capacity(&self) -> usize {
+        K.checked_pow((self.height - 1) as u32)
+            .unwrap_or(usize::MAX)
+    }
+
+    /// Adds a new leaf, rehashing the `K` children of each of its ancestors.
+    /// Returns the index assigned to the leaf.
+    pub fn add_leaf(&mut self, leaf: &Felt) -> Result<usize, MerkleError> {
+        let capacity = self.capacity();
+        let leaf_index = self.len();
+        if leaf_index >= capacity {
+            return Err(MerkleError::TreeFull { capacity });
+        }
+        self.layers[0].push(*leaf);
+        let mut index = leaf_index;
+        for i in 1..self.height {
+            let parent = index / K;
+            let hash_val = hash_children(&self.children(i - 1, parent));
+            if self.layers[i].len() > parent {
+                self.layers[i][parent] = hash_val;
+            } else {
+                self.layers[i].push(hash_val);
+            }
+            index = parent;
+        }
+        Ok(leaf_index)
+    }
+
+    /// Returns the `K` children of node `parent`, padding missing ones with the null hash.
+    fn children(&self, level: usize, parent: usize) -> [Felt; K] {
+        let mut children = [self.precomputed[level]; K];
+        let layer = &self.layers[level];
+        let start = parent * K;
+        let end = layer.len().min(start + K);
+        if start < end {
+            children[..end - start].copy_from_slice(&layer[start..end]);
+        }
+        children
+    }
+
+    /// Returns the current tree root.
+    pub fn root(&self) -> Felt {
+        self.layers[self.height - 1]
+            .first()
+            .copied()
+            .unwrap_or(self.precomputed[self.height - 1])
+    }
+
+    /// Generates a proof for a given leaf index: the `K - 1` siblings of each level in child
+    /// order, and the position of the running node among its siblings.
+    pub fn path(&self, index: usize) -> Result<(Vec<Vec<Felt>>, Vec<usize>), MerkleError> {
+        let len = self.len();
+        if index >= len {
+            return Err(MerkleError::LeafNotFound { index, len });
+        }
+        let mut siblings = Vec::with_capacity(self.height - 1);
+        let mut positions = Vec::with_capacity(self.height - 1);
+        let mut index = index;
+        for i in 0..(self.height - 1) {
+            let position = index % K;
+            let mut level = self.children(i, index / K).to_vec();
+            level.remove(position);
+            siblings.push(level);
+            positions.push(position);
+            index /= K;
+        }
+        Ok((siblings, positions))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::merkle::{HybridMerkleTree, LeafIndex};
+
+    #[test]
+    fn test_binary_kary_tree_matches_hybrid_tree() {
+        let mut kary = KaryMerkleTree::<2>::new(4);
+        let mut tree = HybridMerkleTree::new(4);
+        for i in 1..=5 {
+            let leaf = Felt::from(i as u32);
+            assert_eq!(kary.add_leaf(&leaf), tree.add_leaf(&leaf));
+            assert_eq!(kary.root(), tree.root());
+        }
+        for index in 0..5 {
+            let (siblings, positions) = kary.path(index).unwrap();
+            let (expected, is_right) = tree.path(LeafIndex(index)).unwrap();
+            assert_eq!(siblings.concat(), expected);
+            assert_eq!(
+                positions,
+                is_right.iter().map(|&bit| bit as usize).collect::<Vec<_>>()
+            );
+        }
+    }
+
+    #[test]
+    fn test_quaternary_tree_proofs() {
+        let mut tree = KaryMerkleTree::<4>::new(3);
+        assert_eq!(tree.capacity(), 16);
+        let leaves: Vec<Felt> = (1..=6).map(|i| Felt::from(i as u32)).collect();
+        for leaf in &leaves {
+            tree.add_leaf(leaf).unwrap();
+        }
+        for (index, leaf) in leaves.iter().enumerate() {
+            let (path, _positions) = tree.path(index).unwrap();
+            assert_eq!(path.len(), 2);
+            assert!(path.iter().all(|level| level.len() == 3));
+            assert_eq!(compute_kary_root::<4>(*leaf, index, &path), Ok(tree.root()));
+        }
+
+        let (mut truncated, _positions) = tree.path(5).unwrap();
+        truncated[1].pop();
+        assert_eq!(
+            compute_kary_root::<4>(leaves[5], 5, &truncated),
+            Err(MerkleError::MalformedProof { level: 1 })
+        );
+
+        let null = tree.precomputed[0];
+        let expected = hash_children(&[
+            hash_children(&[leaves[0], leaves[1], leaves[2], leaves[3]]),
+            hash_children(&[leaves[4], leaves[5], null, null]),
+            tree.precomputed[1],
+            tree.precomputed[1],
+        ]);
+        assert_eq!(tree.root(), expected);
+    }
+}
@@ -0,0 +1,18 @@
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+extern crate alloc;
+
+pub mod builder;
+pub mod deferred;
+pub mod error;
+pub mod frontier;
+pub mod hasher;
+pub mod helpers;
+pub mod indexed;
+pub mod kary;
+pub mod merkle;
+pub mod mmr;
+pub mod sparse;
+pub mod store;
+#[cfg(feature = "wasm")]
+pub mod wasm;
@@ -1,23 +1,66 @@
-use starknet_types_core::hash::StarkHash;
+use std::env;
+use std::process;
 
-fn main() {
-    use starknet_types_core::{felt::Felt, hash::Poseidon};
+use noir_merkle_tree::helpers::{
+    check_height, default_null_base, felt_to_bytes, parse_felt, Endianness,
+};
+use starknet_types_core::hash::{Poseidon, StarkHash};
+
+const USAGE: &str =
+    "usage: noir-merkle-tree [--height <levels>] [--base <felt>] [--endianness <big|little>]";
 
-    let mut null_hashes = Vec::new();
+/// Prints the null hashes of a Poseidon tree as a `const` table: level 0 is `--base` (the
+/// default null base if omitted) and each level hashes two copies of the one below.
+/// `--height` defaults to 13 levels; `--base` takes a `0x`-prefixed hex or a decimal value.
+/// Hashes are printed big-endian unless `--endianness little` is given.
+fn main() {
+    let mut height = 13;
+    let mut base = default_null_base();
+    let mut endianness = Endianness::Big;
 
-    let base_null = Poseidon::hash(&Felt::from(0), &Felt::from(0)).to_bytes_be();
-    null_hashes.push(base_null);
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let value = args
+            .next()
+            .unwrap_or_else(|| fail(&format!("{} needs a value", arg)));
+        match arg.as_str() {
+            "--height" => {
+                height = value
+                    .parse()
+                    .unwrap_or_else(|_| fail(&format!("invalid height: {}", value)));
+            }
+            "--base" => {
+                base = parse_felt(&value).unwrap_or_else(|e| fail(&e.to_string()));
+            }
+            "--endianness" => {
+                endianness = match value.as_str() {
+                    "big" => Endianness::Big,
+                    "little" => Endianness::Little,
+                    _ => fail(&format!("invalid endianness: {}", value)),
+                };
+            }
+            _ => fail(&format!("unknown argument: {}", arg)),
+        }
+    }
+    if let Err(e) = check_height(height) {
+        fail(&e.to_string());
+    }
 
-    for i in 1..=12 {
-        let parent_hash = Poseidon::hash(
-            &Felt::from_bytes_be(&null_hashes[i - 1]),
-            &Felt::from_bytes_be(&null_hashes[i - 1]),
-        );
-        null_hashes.push(parent_hash.to_bytes_be());
+    let mut null_hashes = Vec::with_capacity(height);
+    null_hashes.push(felt_to_bytes(&base, endianness));
+    let mut current = base;
+    for _ in 1..height {
+        current = Poseidon::hash(&current, &current);
+        null_hashes.push(felt_to_bytes(&current, endianness));
     }
 
-    println!("const NULL_HASHES: [[u8; 32]; 13] = {:?};", null_hashes);
+    println!(
+        "const NULL_HASHES: [[u8; 32]; {}] = {:?};",
+        height, null_hashes
+    );
 }
 
-pub mod helpers;
-pub mod merkle;
+fn fail(message: &str) -> ! {
+    eprintln!("{}\n{}", message, USAGE);
+    process::exit(2);
+}
@@ -1,223 +1,5073 @@
-use starknet_types_core::{
-    felt::Felt,
-    hash::{Poseidon, StarkHash},
+use alloc::collections::BTreeSet;
+use alloc::string::String;
+use alloc::vec::Vec;
+use alloc::{format, vec};
+use core::fmt;
+use core::marker::PhantomData;
+use core::ops::Range;
+#[cfg(feature = "std")]
+use std::collections::HashMap as LeafIndexMap;
+
+#[cfg(not(feature = "std"))]
+use alloc::collections::BTreeMap as LeafIndexMap;
+
+use starknet_types_core::felt::Felt;
+
+use crate::builder::MerkleTreeBuilder;
+use crate::error::MerkleError;
+use crate::hasher::{MerkleHasher, PoseidonHasher};
+use crate::helpers::{
+    assert_height, bits_from_index, check_height, compute_merkle_root_sorted,
+    compute_root_from_bits, default_null_base, felt_from_bytes, felt_to_bytes, hash_leaf_data,
+    height_for, parse_felt, precomputed_hashes, tag_leaf, verify_proof, Endianness,
 };
+use crate::store::{NodeStore, SharedStore, VecStore};
+
+/// Index of a leaf in a tree, kept apart from heights, levels and counts so that they cannot
+/// be passed for one another. Every method of `HybridMerkleTree` taking a single leaf takes
+/// one; leaf counts, ranges and lists of indices stay `usize`, as do the other tree types and
+/// the wasm bindings, whose indices come from JavaScript numbers. Convert from and to `usize`
+/// with `From`:
+///
+/// ```
+/// use noir_merkle_tree::merkle::{HybridMerkleTree, LeafIndex};
+/// # use starknet_types_core::felt::Felt;
+///
+/// let mut tree = HybridMerkleTree::new(3);
+/// tree.add_leaf(&Felt::from(1)).unwrap();
+/// assert!(tree.path(LeafIndex(0)).is_ok());
+/// assert!(tree.path(0.into()).is_ok());
+/// ```
+///
+/// A raw `usize`, such as the tree height, is not accepted where a leaf index is expected:
+///
+/// ```compile_fail
+/// use noir_merkle_tree::merkle::HybridMerkleTree;
+///
+/// let tree = HybridMerkleTree::new(3);
+/// let _ = tree.path(tree.height());
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct LeafIndex(pub usize);
+
+impl From<usize> for LeafIndex {
+    fn from(index: usize) -> Self {
+        LeafIndex(index)
+    }
+}
+
+impl From<LeafIndex> for usize {
+    fn from(index: LeafIndex) -> Self {
+        index.0
+    }
+}
+
+impl fmt::Display for LeafIndex {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// Inclusion proof for a single leaf: the leaf, its index, the sibling hashes from the
+/// leaf level upwards, and whether the running hash is the right child at each level.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MerkleProof {
+    pub leaf: Felt,
+    pub index: LeafIndex,
+    pub siblings: Vec<Felt>,
+    pub is_right: Vec<bool>,
+}
+
+impl MerkleProof {
+    /// Checks the proof against `root`. The side indicators must agree with `index`.
+    pub fn verify(&self, root: &Felt) -> bool {
+        self.verify_with_hasher::<PoseidonHasher>(root)
+    }
+
+    /// Same as `verify`, for a proof of a tree combining nodes with `H`.
+    pub fn verify_with_hasher<H: MerkleHasher>(&self, root: &Felt) -> bool {
+        if self.is_right.len() != self.siblings.len() {
+            return false;
+        }
+        let bits_match = self.is_right.iter().enumerate().all(|(level, &is_right)| {
+            is_right == (self.index.0.checked_shr(level as u32).unwrap_or(0) & 1 == 1)
+        });
+        bits_match
+            && verify_proof::<H>(
+                root,
+                &self.leaf,
+                self.index.0,
+                &self.siblings,
+                self.siblings.len() + 1,
+            ) == Ok(true)
+    }
+
+    /// Same as `verify`, for a tree built with `SiblingOrder::Sorted`: the side indicators
+    /// and the index are not needed, only the leaf and its siblings.
+    pub fn verify_sorted(&self, root: &Felt) -> bool {
+        self.verify_sorted_with_hasher::<PoseidonHasher>(root)
+    }
+
+    /// Same as `verify_sorted`, for a proof of a tree combining nodes with `H`.
+    pub fn verify_sorted_with_hasher<H: MerkleHasher>(&self, root: &Felt) -> bool {
+        compute_merkle_root_sorted::<H>(self.leaf, &self.siblings) == *root
+    }
+
+    /// Checks that the proof is well-formed for a tree of `height`, without a root: it must
+    /// have `height - 1` siblings and side indicators, an index that fits in the tree, side
+    /// indicators that agree with the index, and no zero sibling above the leaf level, which
+    /// null hashes never are and real nodes only are with negligible probability.
+    pub fn validate_shape(&self, height: usize) -> Result<(), MerkleError> {
+        check_height(height)?;
+        let expected = height - 1;
+        for actual in [self.siblings.len(), self.is_right.len()] {
+            if actual != expected {
+                return Err(MerkleError::ProofLengthMismatch { expected, actual });
+            }
+        }
+        if self.index.0.checked_shr(expected as u32).unwrap_or(0) != 0 {
+            return Err(MerkleError::IndexOutOfRange {
+                index: self.index.0,
+                capacity: 1usize.checked_shl(expected as u32).unwrap_or(usize::MAX),
+            });
+        }
+        let bits = bits_from_index(self.index.0, height);
+        for (level, &bit) in bits.iter().enumerate() {
+            if self.is_right[level] != bit || (level > 0 && self.siblings[level] == Felt::ZERO) {
+                return Err(MerkleError::MalformedProof { level });
+            }
+        }
+        Ok(())
+    }
+
+    /// Renders the proof and `root` as a `Prover.toml` fragment for `nargo`.
+    /// Felts are quoted `0x`-prefixed 64-char hex strings and the index is a decimal integer.
+    pub fn to_prover_toml(&self, root: &Felt) -> String {
+        let hash_path: Vec<String> = self
+            .siblings
+            .iter()
+            .map(|sibling| format!("\"{}\"", sibling.to_fixed_hex_string()))
+            .collect();
+        format!(
+            "leaf = \"{}\"\nindex = {}\nhash_path = [{}]\nroot = \"{}\"\n",
+            self.leaf.to_fixed_hex_string(),
+            self.index.0,
+            hash_path.join(", "),
+            root.to_fixed_hex_string()
+        )
+    }
+
+    /// Renders the proof for test failures and logs: a `leaf=0x..., index=<i>` line, then one
+    /// `L<i>: sibling=0x..., side=right/left` line per level, giving the running hash's side.
+    pub fn describe(&self) -> String {
+        let mut out = format!(
+            "leaf={}, index={}",
+            self.leaf.to_fixed_hex_string(),
+            self.index.0
+        );
+        for (level, sibling) in self.siblings.iter().enumerate() {
+            let side = match self.is_right.get(level) {
+                Some(true) => "right",
+                Some(false) => "left",
+                None => "missing",
+            };
+            out.push_str(&format!(
+                "\nL{}: sibling={}, side={}",
+                level,
+                sibling.to_fixed_hex_string(),
+                side
+            ));
+        }
+        out
+    }
+
+    /// Encodes the proof as bytes: the index (8 bytes, little-endian), the leaf (32 bytes,
+    /// big-endian), the sibling count (4 bytes, little-endian), each sibling (32 bytes,
+    /// big-endian), then the side indicators packed eight per byte, least significant bit first.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.to_bytes_with(Endianness::Big)
+    }
+
+    /// Same as `to_bytes`, with the leaf and siblings encoded in `endianness` order. The index
+    /// and sibling count stay little-endian.
+    pub fn to_bytes_with(&self, endianness: Endianness) -> Vec<u8> {
+        let count = self.siblings.len();
+        let mut bytes = Vec::with_capacity(Self::encoded_len(count));
+        bytes.extend_from_slice(&(self.index.0 as u64).to_le_bytes());
+        bytes.extend_from_slice(&felt_to_bytes(&self.leaf, endianness));
+        bytes.extend_from_slice(&(count as u32).to_le_bytes());
+        for sibling in &self.siblings {
+            bytes.extend_from_slice(&felt_to_bytes(sibling, endianness));
+        }
+        let mut packed = vec![0u8; count.div_ceil(8)];
+        for (level, _) in self
+            .is_right
+            .iter()
+            .take(count)
+            .enumerate()
+            .filter(|(_, &bit)| bit)
+        {
+            packed[level / 8] |= 1 << (level % 8);
+        }
+        bytes.extend_from_slice(&packed);
+        bytes
+    }
+
+    /// Decodes a proof written by `to_bytes`. Fails with `InvalidProofEncoding` if `bytes` is
+    /// not exactly as long as its sibling count requires.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, MerkleError> {
+        Self::from_bytes_with(bytes, Endianness::Big)
+    }
+
+    /// Decodes a proof written by `to_bytes_with` with the same `endianness`.
+    pub fn from_bytes_with(bytes: &[u8], endianness: Endianness) -> Result<Self, MerkleError> {
+        const HEADER: usize = 8 + 32 + 4;
+        let invalid = |expected| MerkleError::InvalidProofEncoding {
+            expected,
+            actual: bytes.len(),
+        };
+        if bytes.len() < HEADER {
+            return Err(invalid(HEADER));
+        }
+        let count = u32::from_le_bytes(bytes[40..HEADER].try_into().unwrap()) as usize;
+        let expected = Self::encoded_len(count);
+        if bytes.len() != expected {
+            return Err(invalid(expected));
+        }
+        let index = u64::from_le_bytes(bytes[..8].try_into().unwrap());
+        let felt = |offset: usize| {
+            felt_from_bytes(&bytes[offset..offset + 32].try_into().unwrap(), endianness)
+        };
+        let packed = &bytes[HEADER + 32 * count..];
+        Ok(MerkleProof {
+            leaf: felt(8),
+            index: LeafIndex(usize::try_from(index).unwrap_or(usize::MAX)),
+            siblings: (0..count).map(|i| felt(HEADER + 32 * i)).collect(),
+            is_right: (0..count)
+                .map(|level| packed[level / 8] >> (level % 8) & 1 == 1)
+                .collect(),
+        })
+    }
+
+    /// Converts the proof to the OpenZeppelin layout: the siblings as big-endian `bytes32`, and
+    /// a directions bitmap whose bit `i` (least significant first) is set when the running hash
+    /// is the right child at level `i`. Fails with `ProofLengthMismatch` beyond 64 levels, which
+    /// the bitmap cannot hold.
+    pub fn to_oz_format(&self) -> Result<(Vec<[u8; 32]>, u64), MerkleError> {
+        if self.is_right.len() > 64 {
+            return Err(MerkleError::ProofLengthMismatch {
+                expected: 64,
+                actual: self.is_right.len(),
+            });
+        }
+        let siblings = self.siblings.iter().map(Felt::to_bytes_be).collect();
+        let directions = self
+            .is_right
+            .iter()
+            .enumerate()
+            .fold(0u64, |bits, (level, &is_right)| {
+                bits | (is_right as u64) << level
+            });
+        Ok((siblings, directions))
+    }
+
+    /// Inverse of `to_oz_format` for the proof of `leaf`, whose index is read back from
+    /// `directions`. Fails with `ProofLengthMismatch` beyond 64 levels, and with
+    /// `IndexOutOfRange` if `directions` has bits set above the last level.
+    pub fn from_oz_format(
+        leaf: Felt,
+        siblings: &[[u8; 32]],
+        directions: u64,
+    ) -> Result<Self, MerkleError> {
+        let count = siblings.len();
+        if count > 64 {
+            return Err(MerkleError::ProofLengthMismatch {
+                expected: 64,
+                actual: count,
+            });
+        }
+        if directions.checked_shr(count as u32).unwrap_or(0) != 0 {
+            return Err(MerkleError::IndexOutOfRange {
+                index: usize::try_from(directions).unwrap_or(usize::MAX),
+                capacity: 1usize.checked_shl(count as u32).unwrap_or(usize::MAX),
+            });
+        }
+        Ok(MerkleProof {
+            leaf,
+            index: LeafIndex(usize::try_from(directions).unwrap_or(usize::MAX)),
+            siblings: siblings.iter().map(Felt::from_bytes_be).collect(),
+            is_right: (0..count)
+                .map(|level| directions >> level & 1 == 1)
+                .collect(),
+        })
+    }
+
+    /// Length of `to_bytes` for a proof of `count` siblings.
+    fn encoded_len(count: usize) -> usize {
+        (8 + 32 + 4 + count.div_ceil(8)).saturating_add(count.saturating_mul(32))
+    }
+}
+
+/// Inclusion proof for several leaves at once. Siblings that can be recomputed from the
+/// proven leaves are omitted; the rest are listed level by level, in ascending index order.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MultiProof {
+    pub height: usize,
+    pub siblings: Vec<Felt>,
+}
+
+/// Inclusion proof of a single leaf whose siblings equal to the null hash of their level are
+/// replaced by `None`, verified with `verify_compressed`. Proofs of sparse trees are mostly
+/// null siblings, so they shrink to the few levels holding other leaves.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompressedProof {
+    pub siblings: Vec<Option<Felt>>,
+}
 
-use crate::helpers::precomputed_hashes;
+/// Inclusion proof for a contiguous range of leaves: only the siblings on the boundary of the
+/// range are listed, level by level, the left one before the right one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RangeProof {
+    pub height: usize,
+    pub siblings: Vec<Felt>,
+}
+
+/// Snapshot of a tree's append state, restored with `HybridMerkleTree::rollback`.
+/// Only the last node of each layer can change on append, so that is all it keeps.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Checkpoint {
+    free_index: usize,
+    left_path: Vec<Felt>,
+    layer_lens: Vec<usize>,
+    layer_tails: Vec<Option<Felt>>,
+    shrink_count: usize, // Shrinks of the tree before the checkpoint was taken.
+}
+
+/// Root of a tree at the moment `HybridMerkleTree::commit` recorded it, with its leaf count.
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RootCommitment {
+    pub root: Felt,
+    pub leaf_count: usize,
+    pub time: std::time::SystemTime,
+}
+
+/// Side on which a partially filled tree is padded with null hashes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Padding {
+    /// Leaves fill the tree from the left and nulls pad the right.
+    #[default]
+    Right,
+    /// Leaves fill the tree from the right, leaf `i` sitting at position `capacity - 1 - i`.
+    Left,
+}
+
+/// Order in which two sibling nodes are passed to the hasher.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum SiblingOrder {
+    /// The left child first, as in the Noir circuit.
+    #[default]
+    Positional,
+    /// The numerically smaller node first, as in OpenZeppelin-style verifiers. Proofs do not
+    /// depend on the side of each sibling, see `compute_merkle_root_sorted`.
+    Sorted,
+}
 
 /// HybridMerkleTree builds the tree dynamically only for added leaves.
+/// Nodes are combined with `H`, Poseidon by default, and kept in `S`, in memory by default.
+///
+/// Empty slots are told apart from leaves by position, never by value: a leaf equal to the
+/// null base is stored, found and proven like any other. Its root is the same as if the slot
+/// were still empty, though, so a proof of the null base also verifies at every empty index.
+/// Membership checks on such values, as in nullifier sets, must also check the proven index
+/// against `len`, or tag the leaves with a leaf domain.
 #[derive(Debug, Clone)]
-pub struct HybridMerkleTree {
+pub struct HybridMerkleTree<H: MerkleHasher = PoseidonHasher, S: NodeStore = VecStore> {
     height: usize,
     precomputed: Vec<Felt>,
     left_path: Vec<Felt>,
-    layers: Vec<Vec<Felt>>, // Each layer stores computed hashes.
-    free_index: usize,      // Number of leaves added.
+    store: S,
+    free_index: usize, // Number of leaves added.
+    root_history: Option<RootHistory>,
+    index_map: Option<LeafIndexMap<Felt, usize>>, // First index of each leaf value.
+    reject_duplicates: bool,
+    grafts: Vec<(usize, usize)>, // Level and index of each root grafted with `add_subtree`.
+    // Number and new length of the shrinks that no later shrink went below, oldest first,
+    // so that lengths increase; `shrink_count` counts every shrink.
+    shrinks: Vec<(usize, usize)>,
+    shrink_count: usize,
+    leaf_domain: Option<Felt>,
+    padding: Padding,
+    sibling_order: SiblingOrder,
+    #[cfg(feature = "metrics")]
+    hash_count: usize, // Hashes computed since construction.
+    #[cfg(feature = "std")]
+    commitments: Vec<RootCommitment>, // Roots recorded by `commit`, oldest first.
+    hasher: PhantomData<H>,
+}
+
+/// Height of `HybridMerkleTree::default()`: 32 levels, room for 2^31 leaves.
+pub const DEFAULT_HEIGHT: usize = 32;
+
+/// Roots recorded after each append; `roots[i]` is the root at `start + i` leaves.
+#[derive(Debug, Clone)]
+struct RootHistory {
+    start: usize,
+    roots: Vec<Felt>,
 }
 
 impl HybridMerkleTree {
     pub fn new(height: usize) -> Self {
-        let precomputed = precomputed_hashes(height);
+        Self::with_hasher(height)
+    }
+
+    /// Creates an empty tree whose empty leaves hash from `base` instead of the default.
+    pub fn with_null_base(height: usize, base: Felt) -> Self {
+        Self::with_hasher_and_null_base(height, base)
+    }
+
+    /// Same as `new`, with room reserved in every layer for `expected_leaves` leaves, so
+    /// that appending them does not reallocate. Reservations are capped at the capacity.
+    pub fn with_capacity(height: usize, expected_leaves: usize) -> Self {
+        Self::with_hasher_and_capacity(height, expected_leaves)
+    }
+
+    /// Builds the whole tree from `leaves` level by level, padding odd layers with the
+    /// precomputed null value. Produces the same state as calling `add_leaf` for each leaf.
+    pub fn from_leaves(height: usize, leaves: &[Felt]) -> Result<Self, MerkleError> {
+        Self::from_leaves_with_hasher(height, leaves)
+    }
+
+    /// Computes the root `from_leaves` would produce while keeping only one level in memory.
+    pub fn root_of(height: usize, leaves: &[Felt]) -> Result<Felt, MerkleError> {
+        Self::root_of_with_hasher(height, leaves)
+    }
+
+    /// Root of an empty tree of `height` with the default null base.
+    pub fn empty_root_for(height: usize) -> Felt {
+        Self::empty_root_for_with_hasher(height)
+    }
+
+    /// Starts a `MerkleTreeBuilder` for combining tree options.
+    pub fn builder() -> MerkleTreeBuilder {
+        MerkleTreeBuilder::default()
+    }
+}
+
+impl<H: MerkleHasher> HybridMerkleTree<H> {
+    /// Same as `new`, for a tree combining nodes with `H`.
+    pub fn with_hasher(height: usize) -> Self {
+        Self::with_hasher_and_null_base(height, default_null_base())
+    }
+
+    /// Same as `with_null_base`, for a tree combining nodes with `H`.
+    /// Panics if `height` is 0.
+    pub fn with_hasher_and_null_base(height: usize, base: Felt) -> Self {
+        assert_height(height);
+        let precomputed = precomputed_hashes::<H>(height, base);
         let left_path = precomputed.clone();
-        let layers = vec![Vec::new(); height];
         Self {
             height,
             precomputed,
             left_path,
-            layers,
+            store: VecStore::new(height),
             free_index: 0,
+            root_history: None,
+            index_map: None,
+            reject_duplicates: false,
+            grafts: Vec::new(),
+            shrinks: Vec::new(),
+            shrink_count: 0,
+            leaf_domain: None,
+            padding: Padding::Right,
+            sibling_order: SiblingOrder::Positional,
+            #[cfg(feature = "metrics")]
+            hash_count: 0,
+            #[cfg(feature = "std")]
+            commitments: Vec::new(),
+            hasher: PhantomData,
         }
     }
 
-    /// Adds a new leaf and updates only the affected path to the root.
-    pub fn add_leaf(&mut self, leaf: &Felt) {
-        let mut hash_val = leaf.clone();
-        let mut index = self.free_index;
-        self.free_index += 1;
-
-        // Add the leaf to layer 0.
-        self.layers[0].push(leaf.clone());
-
-        // Compute parent hashes up the tree.
-        for i in 1..self.height {
-            if index % 2 == 0 {
-                // For an even index, combine with the precomputed null value.
-                let combined = Poseidon::hash(&hash_val, &self.precomputed[i - 1]);
-                self.left_path[i - 1] = hash_val.clone();
-                hash_val = combined;
-            } else {
-                // For an odd index, combine with the left sibling from left_path.
-                hash_val = Poseidon::hash(&self.left_path[i - 1], &hash_val);
+    /// Same as `with_capacity`, for a tree combining nodes with `H`.
+    pub fn with_hasher_and_capacity(height: usize, expected_leaves: usize) -> Self {
+        let mut tree = Self::with_hasher(height);
+        let leaves = expected_leaves.min(tree.capacity());
+        if leaves > 0 {
+            for (i, layer) in tree.store.layers.iter_mut().enumerate() {
+                layer.reserve((leaves - 1).checked_shr(i as u32).unwrap_or(0) + 1);
             }
-            index /= 2;
-            if self.layers[i].len() > index {
-                self.layers[i][index] = hash_val.clone();
+        }
+        tree
+    }
+
+    /// Same as `from_leaves`, for a tree combining nodes with `H`.
+    pub fn from_leaves_with_hasher(height: usize, leaves: &[Felt]) -> Result<Self, MerkleError> {
+        check_height(height)?;
+        let mut tree = Self::with_hasher(height);
+        let capacity = tree.capacity();
+        if leaves.len() > capacity {
+            return Err(MerkleError::TreeFull { capacity });
+        }
+        tree.store.layers[0] = leaves.to_vec();
+        for i in 1..height {
+            let null = tree.precomputed[i - 1];
+            let layer = &tree.store.layers[i - 1];
+            #[cfg(feature = "rayon")]
+            let parents = if layer.len() >= PARALLEL_MIN_NODES {
+                parent_layer_par::<H>(layer, &null)
             } else {
-                self.layers[i].push(hash_val.clone());
+                parent_layer::<H>(layer, &null)
+            };
+            #[cfg(not(feature = "rayon"))]
+            let parents = parent_layer::<H>(layer, &null);
+            tree.record_hashes(parents.len());
+            tree.store.layers[i] = parents;
+        }
+        tree.free_index = leaves.len();
+        tree.restore_left_path();
+        Ok(tree)
+    }
+
+    /// Same as `root_of`, for a tree combining nodes with `H`.
+    pub fn root_of_with_hasher(height: usize, leaves: &[Felt]) -> Result<Felt, MerkleError> {
+        check_height(height)?;
+        let precomputed = precomputed_hashes::<H>(height, default_null_base());
+        let capacity = 1usize
+            .checked_shl((height - 1) as u32)
+            .unwrap_or(usize::MAX);
+        if leaves.len() > capacity {
+            return Err(MerkleError::TreeFull { capacity });
+        }
+        if leaves.is_empty() {
+            return Ok(precomputed[height - 1]);
+        }
+        let mut level = Vec::new();
+        for (i, null) in precomputed[..height - 1].iter().enumerate() {
+            level = parent_layer::<H>(if i == 0 { leaves } else { &level }, null);
+        }
+        // A height-1 tree has no parent levels: its single leaf is the root.
+        Ok(level.first().copied().unwrap_or(leaves[0]))
+    }
+
+    /// Same as `empty_root_for`, for a tree combining nodes with `H`.
+    pub fn empty_root_for_with_hasher(height: usize) -> Felt {
+        assert_height(height);
+        precomputed_hashes::<H>(height, default_null_base())[height - 1]
+    }
+
+    /// Iterates over the stored leaves in insertion order, skipping those grafted with
+    /// `add_subtree`, which are not stored.
+    pub fn leaves(&self) -> core::slice::Iter<'_, Felt> {
+        self.store.layers[0].iter()
+    }
+}
+
+impl<S: NodeStore> HybridMerkleTree<PoseidonHasher, S> {
+    /// Opens a tree over `store`, resuming from the nodes it already holds.
+    pub fn with_store(height: usize, store: S) -> Self {
+        Self::with_hasher_and_store(height, store)
+    }
+}
+
+impl<H: MerkleHasher, S: NodeStore> HybridMerkleTree<H, S> {
+    /// Same as `with_store`, for a tree combining nodes with `H`.
+    pub fn with_hasher_and_store(height: usize, store: S) -> Self {
+        assert_height(height);
+        let precomputed = precomputed_hashes::<H>(height, default_null_base());
+        let mut tree = Self {
+            height,
+            left_path: precomputed.clone(),
+            precomputed,
+            free_index: store.len(0),
+            store,
+            root_history: None,
+            index_map: None,
+            reject_duplicates: false,
+            grafts: Vec::new(),
+            shrinks: Vec::new(),
+            shrink_count: 0,
+            leaf_domain: None,
+            padding: Padding::Right,
+            sibling_order: SiblingOrder::Positional,
+            #[cfg(feature = "metrics")]
+            hash_count: 0,
+            #[cfg(feature = "std")]
+            commitments: Vec::new(),
+            hasher: PhantomData,
+        };
+        tree.restore_left_path();
+        tree
+    }
+
+    /// Enables recording the root after every append, starting from the current state,
+    /// so that past roots can be queried with `root_at`.
+    pub fn with_root_history(mut self) -> Self {
+        self.root_history = Some(RootHistory {
+            start: self.free_index,
+            roots: vec![self.root()],
+        });
+        self
+    }
+
+    /// Returns the root the tree had when it held `free_index` leaves, if root history is
+    /// enabled and that state was recorded. Roots are recorded on append only.
+    pub fn root_at(&self, free_index: usize) -> Option<Felt> {
+        let history = self.root_history.as_ref()?;
+        history
+            .roots
+            .get(free_index.checked_sub(history.start)?)
+            .copied()
+    }
+
+    /// Enables a leaf-to-index map so that `index_of` and `contains` do not scan the leaves.
+    /// The map is a `HashMap` with the `std` feature and a `BTreeMap` without it.
+    pub fn with_index_map(mut self) -> Self {
+        let mut map = LeafIndexMap::new();
+        for index in 0..self.free_index {
+            if let Some(leaf) = self.stored(0, index) {
+                map.entry(leaf).or_insert(index);
             }
         }
-        self.left_path[self.height - 1] = hash_val;
+        self.index_map = Some(map);
+        self
     }
 
-    /// Returns the current tree root.
-    pub fn root(&self) -> Felt {
-        self.left_path[self.height - 1].clone()
+    /// Makes inserting a value that is already a leaf fail with `DuplicateLeaf`, for
+    /// commitment sets where a repeated leaf is a double deposit. This applies to `add_leaf`
+    /// and the functions built on it, `add_leaves`, `merge` and `update_leaf`, but not to
+    /// leaves buffered by a `DeferredMerkleTree`. Enables the index map to find duplicates.
+    /// The flag is not serialized, like the index map.
+    pub fn with_reject_duplicates(mut self) -> Self {
+        if self.index_map.is_none() {
+            self = self.with_index_map();
+        }
+        self.reject_duplicates = true;
+        self
     }
 
-    /// Generates a proof (sibling hashes and side indicators) for a given leaf index.
-    /// The proof is returned as a tuple: (vector of sibling hashes, vector of booleans indicating if the sibling is on the right).
-    pub fn path(&self, mut index: usize) -> (Vec<Felt>, Vec<bool>) {
-        if index >= self.layers[0].len() {
-            panic!("Leaf does not exist!");
+    /// Returns the index of the first of `leaves`, already tagged, that is a leaf of the
+    /// tree or occurs earlier in `leaves`, offset by `len`, if duplicates are rejected.
+    fn find_duplicate(&self, leaves: impl IntoIterator<Item = Felt>) -> Option<usize> {
+        if !self.reject_duplicates {
+            return None;
         }
-        let mut elements = Vec::new();
-        let mut indices = Vec::new();
-        // For each level (except the root level), retrieve the sibling from the corresponding layer.
-        for i in 0..(self.height - 1) {
-            let is_right = index % 2 == 1;
-            let sibling = if is_right {
-                // For a right child, the sibling is at index-1 in the same layer.
-                self.layers[i][index - 1].clone()
-            } else {
-                // For a left child, if the right sibling exists, use it; otherwise, use the precomputed null value.
-                if index + 1 < self.layers[i].len() {
-                    self.layers[i][index + 1].clone()
-                } else {
-                    self.precomputed[i].clone()
-                }
-            };
-            elements.push(sibling);
-            indices.push(is_right);
-            index /= 2;
+        let mut seen = LeafIndexMap::new();
+        for (offset, leaf) in leaves.into_iter().enumerate() {
+            if let Some(index) = self.index_of(&leaf) {
+                return Some(index);
+            }
+            if let Some(&earlier) = seen.get(&leaf) {
+                return Some(earlier);
+            }
+            seen.insert(leaf, self.free_index + offset);
         }
-        (elements, indices)
+        None
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::helpers::{compute_merkle_root_rust, precomputed_hashes};
+    /// Fails with `DuplicateLeaf` if duplicates are rejected and `leaf`, once tagged,
+    /// is already a leaf.
+    fn check_new_leaf(&self, leaf: &Felt) -> Result<(), MerkleError> {
+        if !self.reject_duplicates {
+            return Ok(());
+        }
+        match self.find_duplicate([tag_leaf::<H>(leaf, self.leaf_domain.as_ref())]) {
+            Some(index) => Err(MerkleError::DuplicateLeaf { index }),
+            None => Ok(()),
+        }
+    }
 
-    #[test]
-    fn test_hybrid_merkle_tree_root() {
-        let mut tree = HybridMerkleTree::new(3); // 3 levels for 4 leaves
-        let leaf0 = Felt::from(1);
-        let leaf1 = Felt::from(2);
-        let leaf2 = Felt::from(3);
-        let leaf3 = Felt::from(4);
-        tree.add_leaf(&leaf0);
-        tree.add_leaf(&leaf1);
-        tree.add_leaf(&leaf2);
-        tree.add_leaf(&leaf3);
+    /// Tags every leaf inserted from now on as `H(domain, leaf)` so that leaves and internal
+    /// nodes live in separate hash domains. Leaves are stored, returned and looked up in
+    /// their tagged form; use `compute_merkle_root_with_domain` to verify a raw leaf.
+    pub fn with_leaf_domain(mut self, domain: Felt) -> Self {
+        self.leaf_domain = Some(domain);
+        self
+    }
 
-        let root = tree.root();
-        assert_ne!(
-            root,
-            precomputed_hashes(3)[2],
-            "Root should not be the default null value"
-        );
+    /// Pads the tree on the side given by `padding`. Only used by `MerkleTreeBuilder`, as the
+    /// stored hashes of a non-empty tree would not match the new padding.
+    pub(crate) fn with_padding(mut self, padding: Padding) -> Self {
+        assert!(self.is_empty(), "Padding can only be set on an empty tree!");
+        self.padding = padding;
+        self
     }
 
-    #[test]
-    fn test_compute_merkle_root_rust_simulation() {
-        let height = 3;
-        let mut tree = HybridMerkleTree::new(height);
-        let leaf0 = Felt::from(1);
-        let leaf1 = Felt::from(2);
-        let leaf2 = Felt::from(3);
-        let leaf3 = Felt::from(4);
-        tree.add_leaf(&leaf0);
-        tree.add_leaf(&leaf1);
-        tree.add_leaf(&leaf2);
-        tree.add_leaf(&leaf3);
-
-        let index = 0u32;
-        let (proof, _bits) = tree.path(index as usize);
-        let computed_root = compute_merkle_root_rust(leaf0.clone(), index, &proof);
-        let tree_root = tree.root();
-        assert_eq!(
-            computed_root, tree_root,
-            "Computed root from proof does not match tree root"
+    /// Returns the side on which the tree is padded with null hashes.
+    pub fn padding(&self) -> Padding {
+        self.padding
+    }
+
+    /// Hashes siblings in `order`. Only used by `MerkleTreeBuilder`, as the stored hashes of
+    /// a non-empty tree would not match the new order.
+    pub(crate) fn with_sibling_order(mut self, order: SiblingOrder) -> Self {
+        assert!(
+            self.is_empty(),
+            "Sibling order can only be set on an empty tree!"
         );
+        self.sibling_order = order;
+        self
     }
 
-    #[test]
-    fn test_high_depth_tree_memory() {
-        let height = 32;
-        let mut tree = HybridMerkleTree::new(height);
-        let num_leaves = 100;
-        for i in 0..num_leaves {
-            tree.add_leaf(&Felt::from(i as u32));
+    /// Returns the order in which sibling nodes are hashed.
+    pub fn sibling_order(&self) -> SiblingOrder {
+        self.sibling_order
+    }
+
+    /// Position of leaf `index` among the leaves of the padded tree: `index` itself with right
+    /// padding, `capacity - 1 - index` with left padding. Proofs are verified at this position.
+    /// `index` must be below `capacity`.
+    pub fn position(&self, index: LeafIndex) -> usize {
+        let LeafIndex(index) = index;
+        debug_assert!(index < self.capacity(), "leaf {} is out of range", index);
+        match self.padding {
+            Padding::Right => index,
+            Padding::Left => self.capacity() - 1 - index,
         }
-        assert_eq!(tree.layers[0].len(), num_leaves);
-        for (i, layer) in tree.layers.iter().enumerate() {
-            let expected_max = ((num_leaves as f64) / (2.0f64.powi(i as i32))).ceil() as usize;
-            assert!(
-                layer.len() <= expected_max,
-                "Layer {} has {} elements, expected at most {}",
-                i,
-                layer.len(),
-                expected_max
-            );
+    }
+
+    /// Adds `count` to the hash counter read by `hash_count`, if the `metrics` feature is on.
+    fn record_hashes(&mut self, count: usize) {
+        #[cfg(feature = "metrics")]
+        {
+            self.hash_count += count;
         }
-        assert_ne!(
-            tree.root(),
-            tree.precomputed[height - 1],
-            "Root should not be the default null value"
-        );
+        #[cfg(not(feature = "metrics"))]
+        let _ = count;
     }
 
-    #[test]
-    fn test_compute_merkle_root_high_depth() {
-        let height = 32;
-        let mut tree = HybridMerkleTree::new(height);
-        let leaves: Vec<Felt> = (0..10).map(|i| Felt::from(i as u32 + 1)).collect();
-        for leaf in &leaves {
-            tree.add_leaf(leaf);
+    /// Number of hashes computed by this tree since it was created, excluding the null
+    /// hashes precomputed on construction. `add_leaf` costs `height - 1` hashes, plus one
+    /// with a leaf domain; `from_leaves` costs one per node above the leaves.
+    #[cfg(feature = "metrics")]
+    pub fn hash_count(&self) -> usize {
+        self.hash_count
+    }
+
+    /// Records the current root, leaf count and time as a commitment, and returns it. Unlike
+    /// root history, only the states passed to `commit` are kept, and they outlive `truncate`
+    /// and `clear`, as an audit log of what was published.
+    #[cfg(feature = "std")]
+    pub fn commit(&mut self) -> RootCommitment {
+        let commitment = RootCommitment {
+            root: self.root(),
+            leaf_count: self.free_index,
+            time: std::time::SystemTime::now(),
+        };
+        self.commitments.push(commitment.clone());
+        commitment
+    }
+
+    /// Commitments recorded by `commit`, oldest first.
+    #[cfg(feature = "std")]
+    pub fn commitments(&self) -> &[RootCommitment] {
+        &self.commitments
+    }
+
+    /// Hashes two sibling nodes given in storage order. Left padding mirrors the tree,
+    /// so the stored left node is the logical right child.
+    fn combine(&self, left: &Felt, right: &Felt) -> Felt {
+        self.combine_with(left, right, H::hash)
+    }
+
+    /// Same as `combine`, hashing with `hash` instead of `H`.
+    fn combine_with<F: Fn(&Felt, &Felt) -> Felt>(
+        &self,
+        left: &Felt,
+        right: &Felt,
+        hash: F,
+    ) -> Felt {
+        let (left, right) = match self.padding {
+            Padding::Right => (left, right),
+            Padding::Left => (right, left),
+        };
+        match self.sibling_order {
+            SiblingOrder::Sorted if right < left => hash(right, left),
+            _ => hash(left, right),
         }
-        let index = 0u32;
-        let (proof, _bits) = tree.path(index as usize);
-        let computed_root = compute_merkle_root_rust(leaves[0].clone(), index, &proof);
-        let tree_root = tree.root();
-        assert_eq!(
-            computed_root, tree_root,
-            "Computed root from proof does not match tree root for high depth tree"
-        );
     }
 
-    #[test]
-    #[should_panic(expected = "Leaf does not exist!")]
-    fn test_path_for_nonexistent_leaf() {
-        let tree = HybridMerkleTree::new(3);
-        let _ = tree.path(0);
+    /// Recomputes the root from the stored leaves, combining nodes with `combine` instead of
+    /// `H`: the null hash of each level is `combine` of two copies of the one below, from the
+    /// null base, and the padding and sibling order still apply. Matches `root` when `combine`
+    /// is `H::hash`, so it can instrument or try out another hash without a `MerkleHasher`.
+    pub fn root_with<F: Fn(&Felt, &Felt) -> Felt>(&self, combine: F) -> Felt {
+        let mut null = self.precomputed[0];
+        // Leaves below grafts are unknown; their value is irrelevant, as the grafted root
+        // replaces whatever they hash to.
+        let mut layer: Vec<Felt> = (0..self.free_index)
+            .map(|i| self.stored(0, i).unwrap_or(Felt::ZERO))
+            .collect();
+        for level in 1..self.height {
+            layer = layer
+                .chunks(2)
+                .map(|pair| self.combine_with(&pair[0], pair.get(1).unwrap_or(&null), &combine))
+                .collect();
+            // Grafted roots cannot be recomputed, so they are taken as they are.
+            for &(root_level, root) in &self.grafts {
+                if root_level == level {
+                    layer[root] = self.node(level, root);
+                }
+            }
+            null = combine(&null, &null);
+        }
+        layer.first().copied().unwrap_or(null)
     }
 
-    #[test]
-    fn test_single_leaf_tree() {
-        let mut tree = HybridMerkleTree::new(3);
-        let leaf = Felt::from(42);
-        tree.add_leaf(&leaf);
-        let root = tree.root();
-        assert_ne!(
-            root, tree.precomputed[2],
-            "Root for single leaf should not be the default null value"
-        );
+    /// Returns the index of `leaf`. If the value was inserted more than once, the first
+    /// (lowest) index is returned.
+    pub fn index_of(&self, leaf: &Felt) -> Option<usize> {
+        match &self.index_map {
+            Some(map) => map.get(leaf).copied(),
+            None => (0..self.free_index).find(|&index| self.stored(0, index) == Some(*leaf)),
+        }
     }
 
-    #[test]
-    fn test_path_consistency() {
-        let mut tree = HybridMerkleTree::new(3);
-        let leaf0 = Felt::from(1);
-        let leaf1 = Felt::from(2);
-        tree.add_leaf(&leaf0);
-        tree.add_leaf(&leaf1);
-        let (proof0, bits0) = tree.path(0);
-        let (proof1, bits1) = tree.path(1);
-        assert_ne!(proof0, proof1, "Proofs for different leaves should differ");
-        assert_ne!(
-            bits0, bits1,
+    /// Returns `true` if `leaf` has been added to the tree.
+    pub fn contains(&self, leaf: &Felt) -> bool {
+        self.index_of(leaf).is_some()
+    }
+
+    /// Recomputes `left_path` from the stored layers: for each level it holds the last even
+    /// node on the path of the most recent leaf, and the root at the top.
+    fn restore_left_path(&mut self) {
+        if self.free_index == 0 {
+            self.left_path.copy_from_slice(&self.precomputed);
+            return;
+        }
+        let last = self.free_index - 1;
+        for i in 0..self.height {
+            let index = last.checked_shr(i as u32).unwrap_or(0) & !1;
+            // Below a grafted root, the entry is overwritten before the next append reads it.
+            self.left_path[i] = match self.slot(i, index) {
+                Some(_) => self.node(i, index),
+                None => Felt::ZERO,
+            };
+        }
+    }
+
+    /// Recomputes every upper layer from the stored leaves and restores `left_path`, as
+    /// `from_leaves` would. Use after editing stored nodes directly.
+    pub fn rehash(&mut self) {
+        let mut len = self.free_index;
+        for i in 1..self.height {
+            let null = self.precomputed[i - 1];
+            let parents = len.div_ceil(2);
+            let mut hashed = 0;
+            for index in 0..parents {
+                if self.is_grafted(i, index) {
+                    continue;
+                }
+                hashed += 1;
+                let left = self.node(i - 1, 2 * index);
+                let right = if 2 * index + 1 < len {
+                    self.node(i - 1, 2 * index + 1)
+                } else {
+                    null
+                };
+                let parent = self.combine(&left, &right);
+                self.store_node(i, index, parent);
+            }
+            self.truncate_level(i, parents);
+            self.record_hashes(hashed);
+            len = parents;
+        }
+        self.restore_left_path();
+    }
+
+    /// Grafts the root of a perfect subtree over `1 << subtree_height` leaves committed
+    /// elsewhere, as the node at level `subtree_height`, and returns the range of indices
+    /// its leaves take. The root changes as if the leaves had been added one by one, at the
+    /// cost of `height - 1 - subtree_height` hashes. The tree only knows the grafted root:
+    /// nothing is stored below it, `leaves` skips the grafted leaves, and they cannot be
+    /// read, proven or updated, failing with `PathUnavailable`; the other leaves keep
+    /// working as usual. Grafted leaves are not checked for duplicates. The subtree must
+    /// start at a multiple of its leaf count, and root history restarts after it.
+    pub fn add_subtree(
+        &mut self,
+        subtree_root: &Felt,
+        subtree_height: usize,
+    ) -> Result<Range<usize>, MerkleError> {
+        let capacity = self.capacity();
+        let leaves = 1usize
+            .checked_shl(subtree_height as u32)
+            .filter(|&leaves| leaves <= capacity - self.free_index)
+            .ok_or(MerkleError::TreeFull { capacity })?;
+        let start = self.free_index;
+        if !start.is_multiple_of(leaves) {
+            return Err(MerkleError::MisalignedSubtree {
+                index: start,
+                leaves,
+            });
+        }
+        if subtree_height == 0 {
+            let index = self.append_stored(subtree_root, None);
+            return Ok(index..index + 1);
+        }
+        self.left_path[..subtree_height].fill(Felt::ZERO);
+        let mut index = start >> subtree_height;
+        let mut hash_val = *subtree_root;
+        self.store_node(subtree_height, index, hash_val);
+        for i in (subtree_height + 1)..self.height {
+            if index.is_multiple_of(2) {
+                self.left_path[i - 1] = hash_val;
+                hash_val = self.combine(&hash_val, &self.precomputed[i - 1]);
+            } else {
+                hash_val = self.combine(&self.left_path[i - 1], &hash_val);
+            }
+            index /= 2;
+            self.store_node(i, index, hash_val);
+        }
+        self.left_path[self.height - 1] = hash_val;
+        self.record_hashes(self.height - 1 - subtree_height);
+        self.free_index += leaves;
+        self.grafts.push((subtree_height, start >> subtree_height));
+        if let Some(history) = &mut self.root_history {
+            history.start = self.free_index;
+            history.roots.clear();
+            history.roots.push(hash_val);
+        }
+        Ok(start..self.free_index)
+    }
+
+    /// Returns `true` if the node at `index` of `level` is a root grafted with `add_subtree`
+    /// or lies below one.
+    fn is_grafted(&self, level: usize, index: usize) -> bool {
+        self.grafts
+            .iter()
+            .any(|&(root_level, root)| level <= root_level && index >> (root_level - level) == root)
+    }
+
+    /// Returns `true` if a grafted subtree has some but not all of its leaves below `len`.
+    fn splits_graft(&self, len: usize) -> bool {
+        self.grafts
+            .iter()
+            .any(|&(level, root)| root << level < len && len < (root + 1) << level)
+    }
+
+    /// Adds a new leaf and updates only the affected path to the root.
+    /// Returns the index assigned to the leaf.
+    pub fn add_leaf(&mut self, leaf: &Felt) -> Result<usize, MerkleError> {
+        let capacity = self.capacity();
+        if self.free_index >= capacity {
+            return Err(MerkleError::TreeFull { capacity });
+        }
+        self.check_new_leaf(leaf)?;
+        let index = self.append(leaf, None);
+        #[cfg(debug_assertions)]
+        self.check_invariants();
+        Ok(index)
+    }
+
+    /// Same as `add_leaf`, but returns the updated root instead of the index, for callers that
+    /// commit the root after every insertion.
+    pub fn add_leaf_return_root(&mut self, leaf: &Felt) -> Result<Felt, MerkleError> {
+        self.add_leaf(leaf)?;
+        Ok(self.root())
+    }
+
+    /// Same as `add_leaf`, but also returns the inclusion proof of the new leaf against the
+    /// updated root, collecting its siblings during the insertion instead of walking the tree.
+    pub fn add_leaf_with_proof(
+        &mut self,
+        leaf: &Felt,
+    ) -> Result<(usize, MerkleProof), MerkleError> {
+        let capacity = self.capacity();
+        if self.free_index >= capacity {
+            return Err(MerkleError::TreeFull { capacity });
+        }
+        self.check_new_leaf(leaf)?;
+        let mut siblings = Vec::with_capacity(self.height - 1);
+        let index = self.append(leaf, Some(&mut siblings));
+        let position = self.position(LeafIndex(index));
+        Ok((
+            index,
+            MerkleProof {
+                leaf: self.node(0, index),
+                index: LeafIndex(position),
+                siblings,
+                is_right: bits_from_index(position, self.height),
+            },
+        ))
+    }
+
+    /// Inserts leaves from `leaves` in order and returns the range of indices they were
+    /// assigned. Unlike `add_leaves`, the input is consumed lazily: if the tree fills up, the
+    /// leaves inserted so far are kept and `TreeFull` is returned.
+    pub fn extend<I: IntoIterator<Item = Felt>>(
+        &mut self,
+        leaves: I,
+    ) -> Result<Range<usize>, MerkleError> {
+        let start = self.free_index;
+        for leaf in leaves {
+            self.add_leaf(&leaf)?;
+        }
+        Ok(start..self.free_index)
+    }
+
+    /// Hashes `bytes` with `hash_leaf_data` and adds the result as a leaf.
+    /// Returns the index assigned to the leaf.
+    pub fn add_leaf_data(&mut self, bytes: &[u8]) -> Result<usize, MerkleError> {
+        self.add_leaf(&hash_leaf_data(bytes))
+    }
+
+    /// Parses `s` with `parse_felt` and adds the result as a leaf.
+    /// Returns the index assigned to the leaf.
+    pub fn add_leaf_str(&mut self, s: &str) -> Result<usize, MerkleError> {
+        self.add_leaf(&parse_felt(s)?)
+    }
+
+    /// Inserts all `leaves` in order and returns the range of indices they were assigned.
+    /// Nothing is inserted if the leaves do not all fit, or if one is a rejected duplicate.
+    pub fn add_leaves(&mut self, leaves: &[Felt]) -> Result<Range<usize>, MerkleError> {
+        let capacity = self.capacity();
+        if leaves.len() > capacity - self.free_index {
+            return Err(MerkleError::TreeFull { capacity });
+        }
+        let domain = self.leaf_domain;
+        let tagged = leaves
+            .iter()
+            .map(|leaf| tag_leaf::<H>(leaf, domain.as_ref()));
+        if let Some(index) = self.find_duplicate(tagged) {
+            return Err(MerkleError::DuplicateLeaf { index });
+        }
+        let start = self.free_index;
+        for leaf in leaves {
+            self.append(leaf, None);
+        }
+        Ok(start..self.free_index)
+    }
+
+    /// Appends the leaves of `other` after those of `self`, so the root is that of a single tree
+    /// built from both leaf lists. Leaves are copied as `other` stores them, already tagged
+    /// with its leaf domain if it has one. Nothing is appended if they do not all fit, or if
+    /// one is a rejected duplicate.
+    pub fn merge<T: NodeStore>(
+        &mut self,
+        other: &HybridMerkleTree<H, T>,
+    ) -> Result<(), MerkleError> {
+        if other.height != self.height {
+            return Err(MerkleError::HeightMismatch {
+                expected: self.height,
+                actual: other.height,
+            });
+        }
+        let capacity = self.capacity();
+        if other.free_index > capacity - self.free_index {
+            return Err(MerkleError::TreeFull { capacity });
+        }
+        if let Some(index) = self.find_duplicate((0..other.free_index).map(|i| other.node(0, i))) {
+            return Err(MerkleError::DuplicateLeaf { index });
+        }
+        if let Some(&(level, root)) = other.grafts.first() {
+            return Err(MerkleError::PathUnavailable {
+                index: root << level,
+            });
+        }
+        for index in 0..other.free_index {
+            self.append_stored(&other.node(0, index), None);
+        }
+        Ok(())
+    }
+
+    /// Returns the `(level, index)` of the first stored node that differs from `other`,
+    /// scanning from the leaves up and by index within a level, or `None` if the roots match.
+    /// A node stored by only one of the trees differs. Trees whose nodes all match but whose
+    /// roots do not (different heights or null bases) differ at `self`'s root, `(height - 1, 0)`.
+    pub fn diff_root<T: NodeStore>(
+        &self,
+        other: &HybridMerkleTree<H, T>,
+    ) -> Option<(usize, usize)> {
+        for level in 0..self.height.min(other.height) {
+            let nodes = |len: usize| match len {
+                0 => 0,
+                n => (n - 1).checked_shr(level as u32).unwrap_or(0) + 1,
+            };
+            let len = nodes(self.free_index).max(nodes(other.free_index));
+            if let Some(index) =
+                (0..len).find(|&index| self.stored(level, index) != other.stored(level, index))
+            {
+                return Some((level, index));
+            }
+        }
+        (self.root() != other.root()).then_some((self.height - 1, 0))
+    }
+
+    /// Removes every leaf while keeping the allocated layers for reuse.
+    pub fn clear(&mut self) {
+        for level in 0..self.height {
+            self.truncate_level(level, 0);
+        }
+        self.free_index = 0;
+        self.restore_left_path();
+        self.record_shrink();
+        if let Some(history) = &mut self.root_history {
+            history.start = 0;
+            history.roots.clear();
+            history.roots.push(self.left_path[self.height - 1]);
+        }
+        if let Some(map) = &mut self.index_map {
+            map.clear();
+        }
+        self.grafts.clear();
+    }
+
+    /// Captures the current state so that later appends can be undone with `rollback`.
+    pub fn checkpoint(&self) -> Checkpoint {
+        Checkpoint {
+            free_index: self.free_index,
+            left_path: self.left_path.clone(),
+            layer_lens: (0..self.height).map(|i| self.store.len(i)).collect(),
+            layer_tails: (0..self.height)
+                .map(|i| {
+                    let len = self.store.len(i);
+                    len.checked_sub(1).and_then(|last| self.store.get(i, last))
+                })
+                .collect(),
+            shrink_count: self.shrink_count,
+        }
+    }
+
+    /// Discards every leaf appended since `checkpoint` was taken.
+    /// Leaves updated in place after the checkpoint are not restored. A checkpoint is
+    /// invalid once the tree has been truncated, cleared or rolled back below its length,
+    /// even if leaves were appended again since.
+    pub fn rollback(&mut self, checkpoint: Checkpoint) -> Result<(), MerkleError> {
+        // Shrinks are ordered by number and by length, so the first one since the checkpoint
+        // is the shortest.
+        let first_shrink = self
+            .shrinks
+            .partition_point(|&(number, _)| number < checkpoint.shrink_count);
+        let shrunk_below = self
+            .shrinks
+            .get(first_shrink)
+            .is_some_and(|&(_, len)| len < checkpoint.free_index);
+        if checkpoint.layer_lens.len() != self.height
+            || checkpoint.free_index > self.free_index
+            || shrunk_below
+        {
+            return Err(MerkleError::InvalidCheckpoint {
+                checkpoint_len: checkpoint.free_index,
+                len: self.free_index,
+            });
+        }
+        for (level, (&len, tail)) in checkpoint
+            .layer_lens
+            .iter()
+            .zip(checkpoint.layer_tails)
+            .enumerate()
+        {
+            self.store.truncate(level, len);
+            if let (Some(last), Some(tail)) = (len.checked_sub(1), tail) {
+                self.store.set(level, last, tail);
+            }
+        }
+        self.free_index = checkpoint.free_index;
+        self.left_path = checkpoint.left_path;
+        self.forget_removed_leaves();
+        Ok(())
+    }
+
+    /// Drops every leaf from index `new_len` onwards, rehashing only the path of the new last
+    /// leaf. Unlike `rollback`, this needs no checkpoint.
+    pub fn truncate(&mut self, new_len: usize) -> Result<(), MerkleError> {
+        if new_len > self.free_index || self.splits_graft(new_len) {
+            return Err(MerkleError::TruncateOutOfRange {
+                new_len,
+                len: self.free_index,
+            });
+        }
+        if new_len == self.free_index {
+            return Ok(());
+        }
+        let mut len = new_len;
+        self.truncate_level(0, len);
+        for i in 1..self.height {
+            let parents = len.div_ceil(2);
+            self.truncate_level(i, parents);
+            if let Some(last) = parents
+                .checked_sub(1)
+                .filter(|&last| !self.is_grafted(i, last))
+            {
+                // The last parent may have lost its right child, or had a child rehashed.
+                let left = self.node(i - 1, 2 * last);
+                let right = self
+                    .store
+                    .get(i - 1, 2 * last + 1)
+                    .unwrap_or(self.precomputed[i - 1]);
+                let parent = self.combine(&left, &right);
+                self.store_node(i, last, parent);
+                self.record_hashes(1);
+            }
+            len = parents;
+        }
+        self.free_index = new_len;
+        self.restore_left_path();
+        self.forget_removed_leaves();
+        Ok(())
+    }
+
+    /// Records that the tree shrank to `free_index` leaves, invalidating longer checkpoints.
+    fn record_shrink(&mut self) {
+        let len = self.free_index;
+        while self.shrinks.last().is_some_and(|&(_, last)| last >= len) {
+            self.shrinks.pop();
+        }
+        self.shrinks.push((self.shrink_count, len));
+        self.shrink_count += 1;
+    }
+
+    /// Drops the recorded roots and index map entries of leaves past `free_index`.
+    fn forget_removed_leaves(&mut self) {
+        self.record_shrink();
+        if let Some(history) = &mut self.root_history {
+            if self.free_index < history.start {
+                // Every recorded root had leaves that are now gone.
+                history.start = self.free_index;
+                history.roots.clear();
+                history.roots.push(self.left_path[self.height - 1]);
+            } else {
+                history.roots.truncate(self.free_index - history.start + 1);
+            }
+        }
+        if let Some(map) = &mut self.index_map {
+            let len = self.free_index;
+            map.retain(|_, index| *index < len);
+        }
+        let len = self.free_index;
+        self.grafts
+            .retain(|&(level, root)| (root + 1) << level <= len);
+    }
+
+    /// Appends a leaf without checking capacity and returns its index.
+    /// The siblings met on the way up are pushed to `siblings`, if given.
+    fn append(&mut self, leaf: &Felt, siblings: Option<&mut Vec<Felt>>) -> usize {
+        let leaf = tag_leaf::<H>(leaf, self.leaf_domain.as_ref());
+        self.record_hashes(self.leaf_domain.is_some() as usize);
+        self.append_stored(&leaf, siblings)
+    }
+
+    /// Appends `leaves`, which must fit, then rehashes each upper layer once from the first
+    /// parent they change, as `rehash` would for those parents only. This costs one hash per
+    /// changed node instead of `height - 1` per leaf. Trees recording root history need the
+    /// root after every leaf, so they append one leaf at a time instead.
+    pub(crate) fn append_batch(&mut self, leaves: &[Felt]) {
+        if self.root_history.is_some() {
+            for leaf in leaves {
+                self.append(leaf, None);
+            }
+            return;
+        }
+        if leaves.is_empty() {
+            return;
+        }
+        let mut first = self.free_index;
+        for leaf in leaves {
+            let leaf = tag_leaf::<H>(leaf, self.leaf_domain.as_ref());
+            self.record_hashes(self.leaf_domain.is_some() as usize);
+            self.store_node(0, self.free_index, leaf);
+            if let Some(map) = &mut self.index_map {
+                map.entry(leaf).or_insert(self.free_index);
+            }
+            self.free_index += 1;
+        }
+        let mut len = self.free_index;
+        for i in 1..self.height {
+            first /= 2;
+            let parents = len.div_ceil(2);
+            for index in first..parents {
+                let left = self.node(i - 1, 2 * index);
+                let right = if 2 * index + 1 < len {
+                    self.node(i - 1, 2 * index + 1)
+                } else {
+                    self.precomputed[i - 1]
+                };
+                let parent = self.combine(&left, &right);
+                self.store_node(i, index, parent);
+            }
+            self.record_hashes(parents - first);
+            len = parents;
+        }
+        self.restore_left_path();
+    }
+
+    /// Same as `append`, for a leaf already tagged with the leaf domain.
+    fn append_stored(&mut self, leaf: &Felt, mut siblings: Option<&mut Vec<Felt>>) -> usize {
+        self.record_hashes(self.height - 1);
+        let mut hash_val = *leaf;
+        let mut index = self.free_index;
+        self.free_index += 1;
+
+        // Add the leaf to layer 0.
+        self.store_node(0, index, *leaf);
+
+        // Compute parent hashes up the tree: one hash per level, the minimum for an up-to-date
+        // root. The null operand is constant, but the other one is new on every insert, so
+        // there is no earlier result to reuse.
+        for i in 1..self.height {
+            if index.is_multiple_of(2) {
+                // For an even index, combine with the precomputed null value.
+                let combined = self.combine(&hash_val, &self.precomputed[i - 1]);
+                self.left_path[i - 1] = hash_val;
+                hash_val = combined;
+                if let Some(siblings) = siblings.as_deref_mut() {
+                    siblings.push(self.precomputed[i - 1]);
+                }
+            } else {
+                // For an odd index, combine with the left sibling from left_path.
+                hash_val = self.combine(&self.left_path[i - 1], &hash_val);
+                if let Some(siblings) = siblings.as_deref_mut() {
+                    siblings.push(self.left_path[i - 1]);
+                }
+            }
+            index /= 2;
+            // A parent first hashed against a null right sibling is refreshed here.
+            self.store_node(i, index, hash_val);
+        }
+        self.left_path[self.height - 1] = hash_val;
+        if let Some(history) = &mut self.root_history {
+            history.roots.push(hash_val);
+        }
+        if let Some(map) = &mut self.index_map {
+            map.entry(*leaf).or_insert(self.free_index - 1);
+        }
+        self.free_index - 1
+    }
+
+    /// Number of leaves added so far.
+    pub fn len(&self) -> usize {
+        self.free_index
+    }
+
+    /// Returns `true` if no leaf has been added yet.
+    pub fn is_empty(&self) -> bool {
+        self.free_index == 0
+    }
+
+    /// Returns the leaf stored at `index`, if any.
+    pub fn get_leaf(&self, index: LeafIndex) -> Option<Felt> {
+        self.stored(0, index.0)
+    }
+
+    /// Returns the store holding the nodes of the tree.
+    pub fn store(&self) -> &S {
+        &self.store
+    }
+
+    /// Returns the frontier of the tree: for each level, the last even node on the path of the
+    /// most recent leaf, with the root at the top. Together with `len` it is all an append-only
+    /// tree needs to keep growing; see `FrontierMerkleTree::from_frontier`.
+    pub fn frontier(&self) -> &[Felt] {
+        &self.left_path
+    }
+
+    /// Returns the roots of the perfect subtrees the leaves split into, one per bit set in
+    /// `len`, the largest (leftmost) first: the peaks of a Merkle Mountain Range over the same
+    /// leaves. Each peak is the last even node of its level, so they are read from the frontier.
+    pub fn subtree_roots(&self) -> Vec<Felt> {
+        (0..self.height)
+            .rev()
+            .filter(|&level| self.free_index.checked_shr(level as u32).unwrap_or(0) & 1 == 1)
+            .map(|level| self.left_path[level])
+            .collect()
+    }
+
+    /// Returns `(level, node_count)` for every level, the leaves first, to monitor how much
+    /// the store holds. Empty subtrees are never stored, so counts follow the leaf count.
+    pub fn layer_stats(&self) -> Vec<(usize, usize)> {
+        (0..self.height)
+            .map(|level| (level, self.store.len(level)))
+            .collect()
+    }
+
+    /// Total number of nodes held by the store, across all levels.
+    pub fn node_count(&self) -> usize {
+        (0..self.height).map(|level| self.store.len(level)).sum()
+    }
+
+    /// Number of levels in the tree, including the leaf and root levels.
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Maximum number of leaves the tree can hold, `1 << (height - 1)`.
+    pub fn capacity(&self) -> usize {
+        1usize
+            .checked_shl((self.height - 1) as u32)
+            .unwrap_or(usize::MAX)
+    }
+
+    /// Returns the current tree root.
+    pub fn root(&self) -> Felt {
+        self.left_path[self.height - 1]
+    }
+
+    /// Returns the root this tree had before any leaf was added.
+    pub fn empty_root(&self) -> Felt {
+        self.precomputed[self.height - 1]
+    }
+
+    /// Returns the current root as big-endian bytes.
+    pub fn root_bytes(&self) -> [u8; 32] {
+        self.root_bytes_with(Endianness::Big)
+    }
+
+    /// Same as `root_bytes`, in `endianness` order.
+    pub fn root_bytes_with(&self, endianness: Endianness) -> [u8; 32] {
+        felt_to_bytes(&self.root(), endianness)
+    }
+
+    /// Returns the current root as a `0x`-prefixed 64-char hex string.
+    pub fn root_hex(&self) -> String {
+        self.root().to_fixed_hex_string()
+    }
+
+    /// Generates a proof (sibling hashes and side indicators) for a given leaf index.
+    /// The proof is returned as a tuple: (vector of sibling hashes, vector of booleans indicating if the sibling is on the right).
+    /// With left padding the booleans follow the leaf's `position`, not its index.
+    pub fn path(&self, index: LeafIndex) -> Result<(Vec<Felt>, Vec<bool>), MerkleError> {
+        let LeafIndex(index) = index;
+        self.check_index(index)?;
+        self.try_path(index)
+            .ok_or(MerkleError::PathUnavailable { index })
+    }
+
+    /// Same as `path`, but each level also carries the running node hashed with the sibling:
+    /// `(node, sibling, is_right)`, from the leaf upwards. These are the exact hash inputs of
+    /// every level, the node of each level being the hash of the level below.
+    pub fn path_with_nodes(
+        &self,
+        index: LeafIndex,
+    ) -> Result<Vec<(Felt, Felt, bool)>, MerkleError> {
+        let (siblings, is_right) = self.path(index)?;
+        let LeafIndex(index) = index;
+        Ok(siblings
+            .into_iter()
+            .zip(is_right)
+            .enumerate()
+            .map(|(level, (sibling, is_right))| {
+                let node_index = index.checked_shr(level as u32).unwrap_or(0);
+                (self.node(level, node_index), sibling, is_right)
+            })
+            .collect())
+    }
+
+    /// Ensures a leaf has been added at `index`.
+    fn check_index(&self, index: usize) -> Result<(), MerkleError> {
+        let capacity = self.capacity();
+        if index >= capacity {
+            return Err(MerkleError::IndexOutOfRange { index, capacity });
+        }
+        let len = self.free_index;
+        if index >= len {
+            return Err(MerkleError::LeafNotFound { index, len });
+        }
+        if self.is_grafted(0, index) {
+            return Err(MerkleError::PathUnavailable { index });
+        }
+        Ok(())
+    }
+
+    /// Same as `path`, but panics if the leaf does not exist.
+    pub fn path_unchecked(&self, index: LeafIndex) -> (Vec<Felt>, Vec<bool>) {
+        let LeafIndex(index) = index;
+        if index >= self.free_index {
+            panic!("Leaf does not exist!");
+        }
+        self.try_path(index).expect("Node store is missing a node!")
+    }
+
+    /// Builds the path of an existing leaf, or returns `None` if the store is missing a node
+    /// on it, e.g. a custom store that lost part of a level.
+    fn try_path(&self, mut index: usize) -> Option<(Vec<Felt>, Vec<bool>)> {
+        let mut elements = Vec::new();
+        let mut indices = Vec::new();
+        // For each level (except the root level), retrieve the sibling from the corresponding layer.
+        let mirrored = self.padding == Padding::Left;
+        for i in 0..(self.height - 1) {
+            let is_right = (index % 2 == 1) != mirrored;
+            elements.push(self.try_sibling(i, index)?);
+            indices.push(is_right);
+            index /= 2;
+        }
+        Some((elements, indices))
+    }
+
+    /// Panics if the tree is inconsistent: a layer holds more nodes than the leaves cover,
+    /// `left_path` differs from the stored nodes on the spine of the last leaf, or
+    /// `precomputed` does not have one null hash per level.
+    #[cfg(debug_assertions)]
+    fn check_invariants(&self) {
+        debug_assert_eq!(self.precomputed.len(), self.height, "precomputed length");
+        debug_assert_eq!(self.left_path.len(), self.height, "left_path length");
+        for i in 0..self.height {
+            let max = match self.free_index {
+                0 => 0,
+                n => (n - 1).checked_shr(i as u32).unwrap_or(0) + 1,
+            };
+            debug_assert!(
+                self.store.len(i) <= max,
+                "layer {} has {} nodes, expected at most {}",
+                i,
+                self.store.len(i),
+                max
+            );
+        }
+        for i in 0..self.height {
+            let expected = match self.free_index.checked_sub(1) {
+                None => Some(self.precomputed[i]),
+                Some(last) => self
+                    .slot(i, last.checked_shr(i as u32).unwrap_or(0) & !1)
+                    .and_then(|slot| self.store.peek(i, slot)),
+            };
+            // Nodes a store has lost, or cannot peek at, are not checked.
+            if let Some(expected) = expected {
+                debug_assert_eq!(self.left_path[i], expected, "left_path[{}]", i);
+            }
+        }
+    }
+
+    /// Number of nodes of `level` before `index` that are stored, skipping those below the
+    /// grafted roots, which are not.
+    fn slots_before(&self, level: usize, index: usize) -> usize {
+        let mut slots = index;
+        // Grafts are ordered by position and do not overlap.
+        for &(root_level, root) in &self.grafts {
+            if root_level <= level {
+                continue;
+            }
+            let shift = root_level - level;
+            let start = root << shift;
+            if index <= start {
+                break;
+            }
+            slots -= index.min((root + 1) << shift) - start;
+        }
+        slots
+    }
+
+    /// Index in the store of the node at `index` of `level`, or `None` if it lies below a
+    /// grafted root and is not stored.
+    fn slot(&self, level: usize, index: usize) -> Option<usize> {
+        let below_graft = self
+            .grafts
+            .iter()
+            .any(|&(root_level, root)| level < root_level && index >> (root_level - level) == root);
+        (!below_graft).then(|| self.slots_before(level, index))
+    }
+
+    /// Returns the node at `index` of `level`, if stored.
+    fn stored(&self, level: usize, index: usize) -> Option<Felt> {
+        self.store.get(level, self.slot(level, index)?)
+    }
+
+    /// Stores `node` at `index` of `level`, which must not lie below a grafted root.
+    fn store_node(&mut self, level: usize, index: usize, node: Felt) {
+        let slot = self
+            .slot(level, index)
+            .expect("Node is below a grafted root!");
+        self.store.set(level, slot, node);
+    }
+
+    /// Drops every node of `level` from index `len` onwards.
+    fn truncate_level(&mut self, level: usize, len: usize) {
+        let slots = self.slots_before(level, len);
+        self.store.truncate(level, slots);
+    }
+
+    /// Returns the stored node at `index` in layer `level`, which must exist.
+    fn node(&self, level: usize, index: usize) -> Felt {
+        self.stored(level, index)
+            .expect("Node store is missing a node!")
+    }
+
+    /// Returns the sibling of the node at `index` in layer `level`, which must exist.
+    fn sibling(&self, level: usize, index: usize) -> Felt {
+        self.try_sibling(level, index)
+            .expect("Node store is missing a node!")
+    }
+
+    /// Returns the sibling of the node at `index` in layer `level`, or `None` if the store is
+    /// missing it.
+    fn try_sibling(&self, level: usize, index: usize) -> Option<Felt> {
+        if index % 2 == 1 {
+            // For a right child, the sibling is at index-1 in the same layer.
+            self.stored(level, index - 1)
+        } else {
+            // For a left child, use the right sibling if it exists, else the null value, as
+            // long as no leaf lies below it: then it is missing, not empty.
+            let first_leaf = (index + 1).checked_shl(level as u32).unwrap_or(usize::MAX);
+            match self.stored(level, index + 1) {
+                Some(sibling) => Some(sibling),
+                None if first_leaf < self.free_index => None,
+                None => Some(self.precomputed[level]),
+            }
+        }
+    }
+
+    /// Overwrites an existing leaf and recomputes every hash on its path to the root.
+    pub fn update_leaf(&mut self, index: LeafIndex, new_leaf: &Felt) -> Result<(), MerkleError> {
+        let LeafIndex(mut index) = index;
+        self.check_index(index)?;
+        let new_leaf = &tag_leaf::<H>(new_leaf, self.leaf_domain.as_ref());
+        if let Some(existing) = self.find_duplicate([*new_leaf]).filter(|&i| i != index) {
+            return Err(MerkleError::DuplicateLeaf { index: existing });
+        }
+        self.record_hashes(self.height - 1 + self.leaf_domain.is_some() as usize);
+        if self.index_map.is_some() {
+            self.remap_leaf(index, new_leaf);
+        }
+        // left_path[i] mirrors the last even node of layer i on the rightmost path.
+        let last = self.free_index - 1;
+        let mut hash_val = *new_leaf;
+        for i in 0..self.height {
+            self.store_node(i, index, hash_val);
+            if index == last.checked_shr(i as u32).unwrap_or(0) & !1 {
+                self.left_path[i] = hash_val;
+            }
+            if i + 1 < self.height {
+                let sibling = self.sibling(i, index);
+                hash_val = if index.is_multiple_of(2) {
+                    self.combine(&hash_val, &sibling)
+                } else {
+                    self.combine(&sibling, &hash_val)
+                };
+            }
+            index /= 2;
+        }
+        Ok(())
+    }
+
+    /// Points the index map away from the value being replaced at `index`, falling back to
+    /// its next occurrence, and at `index` for `new_leaf` if that is its first occurrence.
+    fn remap_leaf(&mut self, index: usize, new_leaf: &Felt) {
+        let old_leaf = self.node(0, index);
+        let next_old =
+            ((index + 1)..self.free_index).find(|&i| self.stored(0, i) == Some(old_leaf));
+        let Some(map) = &mut self.index_map else {
+            return;
+        };
+        if map.get(&old_leaf) == Some(&index) {
+            match next_old {
+                Some(next) => map.insert(old_leaf, next),
+                None => map.remove(&old_leaf),
+            };
+        }
+        let first = map.entry(*new_leaf).or_insert(index);
+        *first = (*first).min(index);
+    }
+
+    /// Same as `path`, but bundles the leaf and its position into a `MerkleProof`.
+    pub fn proof(&self, index: LeafIndex) -> Result<MerkleProof, MerkleError> {
+        let (siblings, is_right) = self.path(index)?;
+        Ok(MerkleProof {
+            leaf: self.node(0, index.0),
+            index: LeafIndex(self.position(index)),
+            siblings,
+            is_right,
+        })
+    }
+
+    /// Returns the witness of leaf `index` for the Noir `compute_merkle_root` circuit: the leaf,
+    /// its position as a field element and its `height - 1` siblings, the fixed-size
+    /// `hash_path` of the circuit. Sides are implied by the position, as in
+    /// `compute_merkle_root_rust`, so trees with `SiblingOrder::Sorted` do not fit the circuit.
+    pub fn to_noir_witness(
+        &self,
+        index: LeafIndex,
+    ) -> Result<(Felt, Felt, Vec<Felt>), MerkleError> {
+        let (hash_path, _) = self.path(index)?;
+        Ok((
+            self.node(0, index.0),
+            Felt::from(self.position(index)),
+            hash_path,
+        ))
+    }
+
+    /// Brings a proof generated by `proof` up to date with leaves appended since. Appending
+    /// only changes the siblings to the right of the leaf's path, so only those are reloaded.
+    /// Leaves overwritten with `update_leaf` are not accounted for; regenerate the proof instead.
+    pub fn refresh_proof(&self, proof: &mut MerkleProof) -> Result<(), MerkleError> {
+        let expected = self.height - 1;
+        if proof.siblings.len() != expected {
+            return Err(MerkleError::ProofLengthMismatch {
+                expected,
+                actual: proof.siblings.len(),
+            });
+        }
+        // The proof holds a position, which `position` maps back to the leaf index.
+        let capacity = self.capacity();
+        if proof.index.0 >= capacity {
+            return Err(MerkleError::IndexOutOfRange {
+                index: proof.index.0,
+                capacity,
+            });
+        }
+        let mut index = self.position(proof.index);
+        self.check_index(index)?;
+        for (level, sibling) in proof.siblings.iter_mut().enumerate() {
+            if index.is_multiple_of(2) {
+                *sibling = self.sibling(level, index);
+            }
+            index /= 2;
+        }
+        Ok(())
+    }
+
+    /// Same as `proof`, but for the tree as it was when it held `at_len` leaves, so that it
+    /// verifies against `root_at(at_len)`. Siblings covering leaves appended since are
+    /// recomputed from the stored leaves. Like `refresh_proof`, leaves overwritten with
+    /// `update_leaf` are not accounted for.
+    pub fn historical_path(
+        &self,
+        index: LeafIndex,
+        at_len: usize,
+    ) -> Result<MerkleProof, MerkleError> {
+        let LeafIndex(index) = index;
+        if at_len > self.free_index {
+            return Err(MerkleError::InvalidCheckpoint {
+                checkpoint_len: at_len,
+                len: self.free_index,
+            });
+        }
+        self.check_index(index)?;
+        if index >= at_len {
+            return Err(MerkleError::LeafNotFound { index, len: at_len });
+        }
+        if self.splits_graft(at_len) {
+            return Err(MerkleError::PathUnavailable { index });
+        }
+        let mirrored = self.padding == Padding::Left;
+        let mut siblings = Vec::with_capacity(self.height - 1);
+        let mut is_right = Vec::with_capacity(self.height - 1);
+        let mut node = index;
+        for level in 0..(self.height - 1) {
+            let sibling = self
+                .historical_node(level, node ^ 1, at_len)
+                .ok_or(MerkleError::PathUnavailable { index })?;
+            siblings.push(sibling);
+            is_right.push((node % 2 == 1) != mirrored);
+            node /= 2;
+        }
+        Ok(MerkleProof {
+            leaf: self.node(0, index),
+            index: LeafIndex(self.position(LeafIndex(index))),
+            siblings,
+            is_right,
+        })
+    }
+
+    /// Proves that the tree extends its state at `old_len` leaves, so that a client holding
+    /// `root_at(old_len)` can check the current root against it with `verify_consistency`.
+    /// The proof holds leaf `old_len - 1`, then for each level of its path the left sibling,
+    /// unchanged since, or the current right sibling, which was null back then. Only appends
+    /// are accounted for. `verify_consistency` only knows the default padding, sibling order
+    /// and null base, so other trees fail with `UnsupportedConfiguration`.
+    pub fn consistency_proof(&self, old_len: usize) -> Result<Vec<Felt>, MerkleError> {
+        let reason = if self.padding != Padding::Right {
+            Some("consistency proofs need right padding")
+        } else if self.sibling_order != SiblingOrder::Positional {
+            Some("consistency proofs need positional sibling order")
+        } else if self.precomputed[0] != default_null_base() {
+            Some("consistency proofs need the default null base")
+        } else {
+            None
+        };
+        if let Some(reason) = reason {
+            return Err(MerkleError::UnsupportedConfiguration { reason });
+        }
+        if old_len > self.free_index {
+            return Err(MerkleError::InvalidCheckpoint {
+                checkpoint_len: old_len,
+                len: self.free_index,
+            });
+        }
+        if old_len == 0 {
+            return Err(MerkleError::InvalidRange { start: 0, end: 0 });
+        }
+        let last = old_len - 1;
+        if self.is_grafted(0, last) {
+            return Err(MerkleError::PathUnavailable { index: last });
+        }
+        let mut proof = Vec::with_capacity(self.height);
+        proof.push(self.node(0, last));
+        for level in 0..(self.height - 1) {
+            let index = last.checked_shr(level as u32).unwrap_or(0);
+            proof.push(if index % 2 == 1 {
+                self.node(level, index - 1)
+            } else {
+                self.sibling(level, index)
+            });
+        }
+        Ok(proof)
+    }
+
+    /// Returns the node at `index` in layer `level` as it was when the tree held `at_len`
+    /// leaves: the stored node if all its leaves were already there, the null hash if none
+    /// were, and the hash of its historical children otherwise.
+    fn historical_node(&self, level: usize, index: usize, at_len: usize) -> Option<Felt> {
+        let first_leaf = index.checked_shl(level as u32).unwrap_or(usize::MAX);
+        let end_leaf = (index + 1).checked_shl(level as u32).unwrap_or(usize::MAX);
+        if first_leaf >= at_len {
+            Some(self.precomputed[level])
+        } else if end_leaf <= at_len {
+            self.stored(level, index)
+        } else {
+            let left = self.historical_node(level - 1, 2 * index, at_len)?;
+            let right = self.historical_node(level - 1, 2 * index + 1, at_len)?;
+            Some(self.combine(&left, &right))
+        }
+    }
+
+    /// Generates the proof of `leaf`, found with `index_of`, or returns `None` if the value has
+    /// not been added. Fast on trees configured with `with_index_map`.
+    pub fn proof_for_value(&self, leaf: &Felt) -> Option<MerkleProof> {
+        self.index_of(leaf)
+            .and_then(|index| self.proof(LeafIndex(index)).ok())
+    }
+
+    /// Generates a proof for the leaves `start..end`, verified with `verify_range_proof`.
+    /// It is the `multi_path` of the range, which only needs the siblings on its boundary.
+    pub fn range_proof(&self, start: usize, end: usize) -> Result<RangeProof, MerkleError> {
+        if start >= end {
+            return Err(MerkleError::InvalidRange { start, end });
+        }
+        self.check_index(end - 1)?;
+        let indices: Vec<usize> = (start..end).collect();
+        let MultiProof { height, siblings } = self.multi_path(&indices)?;
+        Ok(RangeProof { height, siblings })
+    }
+
+    /// Same as `path`, leaving out the siblings that are the null hash of their level.
+    /// It verifies against the leaf's `position`, with `verify_compressed_with_null_base`
+    /// if the tree does not use the default null base.
+    pub fn compressed_path(&self, index: LeafIndex) -> Result<CompressedProof, MerkleError> {
+        let (siblings, _) = self.path(index)?;
+        Ok(CompressedProof {
+            siblings: siblings
+                .into_iter()
+                .zip(&self.precomputed)
+                .map(|(sibling, null)| (sibling != *null).then_some(sibling))
+                .collect(),
+        })
+    }
+
+    /// Generates a single proof for all leaves at `indices`, sharing common siblings.
+    /// It verifies against the leaves' `position`s.
+    pub fn multi_path(&self, indices: &[usize]) -> Result<MultiProof, MerkleError> {
+        for &index in indices {
+            self.check_index(index)?;
+        }
+        let mut known: BTreeSet<usize> = indices.iter().copied().collect();
+        let mut siblings = Vec::new();
+        for i in 0..(self.height - 1) {
+            // Siblings are listed in ascending position, which is descending index when mirrored.
+            let mut level: Vec<usize> = known.iter().copied().collect();
+            if self.padding == Padding::Left {
+                level.reverse();
+            }
+            for &index in &level {
+                if !known.contains(&(index ^ 1)) {
+                    siblings.push(self.sibling(i, index));
+                }
+            }
+            known = known.iter().map(|index| index / 2).collect();
+        }
+        Ok(MultiProof {
+            height: self.height,
+            siblings,
+        })
+    }
+}
+
+/// Trees are equal when they commit to the same state: the same height, leaves and root.
+/// Derived data (`precomputed`, `left_path`, upper layers) and the opt-in root history and
+/// index map are not compared, so trees built by different insertion strategies compare
+/// equal. The root is compared so that trees with different null bases do not.
+impl<H: MerkleHasher, S: NodeStore> PartialEq for HybridMerkleTree<H, S> {
+    fn eq(&self, other: &Self) -> bool {
+        self.height == other.height
+            && self.free_index == other.free_index
+            && self.root() == other.root()
+            && (0..self.free_index).all(|i| self.stored(0, i) == other.stored(0, i))
+    }
+}
+
+impl<H: MerkleHasher, S: NodeStore> Eq for HybridMerkleTree<H, S> {}
+
+/// An empty Poseidon tree of `DEFAULT_HEIGHT` levels.
+impl Default for HybridMerkleTree {
+    fn default() -> Self {
+        Self::new(DEFAULT_HEIGHT)
+    }
+}
+
+/// A Poseidon tree holding `leaves`, of the smallest height that fits them: see `height_for`.
+impl From<&[Felt]> for HybridMerkleTree {
+    fn from(leaves: &[Felt]) -> Self {
+        Self::from_leaves(height_for(leaves.len()), leaves)
+            .expect("Leaves must fit in a tree of their minimal height!")
+    }
+}
+
+/// Same as `From<&[Felt]>`, for the collected leaves.
+impl FromIterator<Felt> for HybridMerkleTree {
+    fn from_iter<I: IntoIterator<Item = Felt>>(leaves: I) -> Self {
+        let leaves: Vec<Felt> = leaves.into_iter().collect();
+        Self::from(leaves.as_slice())
+    }
+}
+
+impl<'a, H: MerkleHasher> IntoIterator for &'a HybridMerkleTree<H> {
+    type Item = &'a Felt;
+    type IntoIter = core::slice::Iter<'a, Felt>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.leaves()
+    }
+}
+
+impl<H: MerkleHasher> HybridMerkleTree<H, SharedStore> {
+    /// Takes a `ReadSnapshot` of the current state. The snapshot and the tree share their
+    /// chunks of nodes, so this costs one pointer per chunk rather than a copy of the tree;
+    /// chunks the tree writes afterwards are copied on write and the snapshot is unaffected.
+    pub fn snapshot(&self) -> ReadSnapshot<H> {
+        ReadSnapshot {
+            tree: HybridMerkleTree {
+                height: self.height,
+                precomputed: self.precomputed.clone(),
+                left_path: self.left_path.clone(),
+                store: self.store.clone(),
+                free_index: self.free_index,
+                root_history: None,
+                index_map: None,
+                reject_duplicates: false,
+                grafts: self.grafts.clone(),
+                shrinks: Vec::new(),
+                shrink_count: 0,
+                leaf_domain: self.leaf_domain,
+                padding: self.padding,
+                sibling_order: self.sibling_order,
+                #[cfg(feature = "metrics")]
+                hash_count: self.hash_count,
+                #[cfg(feature = "std")]
+                commitments: self.commitments.clone(),
+                hasher: PhantomData,
+            },
+        }
+    }
+}
+
+/// Read-only view of a tree over `SharedStore`, taken with `HybridMerkleTree::snapshot`.
+/// Snapshots can be handed to readers, e.g. behind an `Arc`, while the tree keeps growing.
+#[derive(Debug)]
+pub struct ReadSnapshot<H: MerkleHasher = PoseidonHasher> {
+    tree: HybridMerkleTree<H, SharedStore>,
+}
+
+impl<H: MerkleHasher> ReadSnapshot<H> {
+    /// Number of leaves when the snapshot was taken.
+    pub fn len(&self) -> usize {
+        self.tree.len()
+    }
+
+    /// Returns `true` if the tree was empty when the snapshot was taken.
+    pub fn is_empty(&self) -> bool {
+        self.tree.is_empty()
+    }
+
+    /// See `HybridMerkleTree::height`.
+    pub fn height(&self) -> usize {
+        self.tree.height()
+    }
+
+    /// Root of the tree when the snapshot was taken.
+    pub fn root(&self) -> Felt {
+        self.tree.root()
+    }
+
+    /// See `HybridMerkleTree::get_leaf`.
+    pub fn get_leaf(&self, index: LeafIndex) -> Option<Felt> {
+        self.tree.get_leaf(index)
+    }
+
+    /// See `HybridMerkleTree::path`.
+    pub fn path(&self, index: LeafIndex) -> Result<(Vec<Felt>, Vec<bool>), MerkleError> {
+        self.tree.path(index)
+    }
+
+    /// See `HybridMerkleTree::proof`.
+    pub fn proof(&self, index: LeafIndex) -> Result<MerkleProof, MerkleError> {
+        self.tree.proof(index)
+    }
+
+    /// Checks `proof` against the snapshot's root, hashing with `H` in the tree's sibling order.
+    pub fn verify(&self, proof: &MerkleProof) -> bool {
+        if proof.validate_shape(self.height()).is_err() {
+            return false;
+        }
+        let root = match self.tree.sibling_order {
+            SiblingOrder::Positional => {
+                compute_root_from_bits::<H>(&proof.leaf, &proof.is_right, &proof.siblings)
+            }
+            SiblingOrder::Sorted => compute_merkle_root_sorted::<H>(proof.leaf, &proof.siblings),
+        };
+        root == self.root()
+    }
+}
+
+/// Hashes each pair of nodes in `layer`, pairing a trailing odd node with `null`.
+fn parent_layer<H: MerkleHasher>(layer: &[Felt], null: &Felt) -> Vec<Felt> {
+    layer
+        .chunks(2)
+        .map(|pair| H::hash(&pair[0], pair.get(1).unwrap_or(null)))
+        .collect()
+}
+
+/// Layers smaller than this are hashed sequentially, as splitting them costs more than it saves.
+#[cfg(feature = "rayon")]
+const PARALLEL_MIN_NODES: usize = 1024;
+
+/// Parallel version of `parent_layer`.
+#[cfg(feature = "rayon")]
+fn parent_layer_par<H: MerkleHasher>(layer: &[Felt], null: &Felt) -> Vec<Felt> {
+    use rayon::prelude::*;
+
+    layer
+        .par_chunks(2)
+        .map(|pair| H::hash(&pair[0], pair.get(1).unwrap_or(null)))
+        .collect()
+}
+
+#[cfg(feature = "serde")]
+mod serde_impl {
+    use alloc::string::{String, ToString};
+
+    use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+    use super::*;
+    use crate::hasher::CACHED_NULL_HASHES_HEIGHT;
+
+    /// On-disk form of the tree, with felts written as `F`. `precomputed` and `left_path` are
+    /// derived from these fields.
+    #[derive(Serialize)]
+    pub(super) struct TreeRef<'a, F> {
+        height: usize,
+        null_base: F,
+        free_index: usize,
+        layers: &'a [Vec<F>],
+        padding: Padding,
+        sibling_order: SiblingOrder,
+        grafts: &'a [(usize, usize)],
+        leaf_domain: Option<F>,
+    }
+
+    #[derive(Deserialize)]
+    #[serde(bound(deserialize = "F: Deserialize<'de>"))]
+    pub(super) struct TreeOwned<F> {
+        height: usize,
+        null_base: F,
+        free_index: usize,
+        layers: Vec<Vec<F>>,
+        padding: Padding,
+        sibling_order: SiblingOrder,
+        #[serde(default)]
+        grafts: Vec<(usize, usize)>,
+        #[serde(default)]
+        leaf_domain: Option<F>,
+    }
+
+    #[cfg(feature = "std")]
+    impl<F> TreeOwned<F> {
+        /// Converts every felt with `felt`.
+        pub(super) fn map_felts(self, felt: impl Fn(F) -> Felt) -> TreeOwned<Felt> {
+            TreeOwned {
+                height: self.height,
+                null_base: felt(self.null_base),
+                free_index: self.free_index,
+                layers: self
+                    .layers
+                    .into_iter()
+                    .map(|layer| layer.into_iter().map(&felt).collect())
+                    .collect(),
+                padding: self.padding,
+                sibling_order: self.sibling_order,
+                grafts: self.grafts,
+                leaf_domain: self.leaf_domain.map(&felt),
+            }
+        }
+    }
+
+    /// A felt written as its 32 little-endian bytes. `Felt` itself writes big-endian bytes
+    /// to binary formats.
+    #[cfg(feature = "std")]
+    pub(super) struct LittleEndianFelt(pub(super) Felt);
+
+    #[cfg(feature = "std")]
+    impl Serialize for LittleEndianFelt {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            serializer.serialize_bytes(&self.0.to_bytes_le())
+        }
+    }
+
+    #[cfg(feature = "std")]
+    impl<'de> Deserialize<'de> for LittleEndianFelt {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            struct BytesVisitor;
+
+            impl de::Visitor<'_> for BytesVisitor {
+                type Value = LittleEndianFelt;
+
+                fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                    f.write_str("32 little-endian bytes")
+                }
+
+                fn visit_bytes<E: de::Error>(self, bytes: &[u8]) -> Result<Self::Value, E> {
+                    let bytes: [u8; 32] = bytes
+                        .try_into()
+                        .map_err(|_| E::invalid_length(bytes.len(), &self))?;
+                    Ok(LittleEndianFelt(Felt::from_bytes_le(&bytes)))
+                }
+            }
+
+            deserializer.deserialize_bytes(BytesVisitor)
+        }
+    }
+
+    impl<H: MerkleHasher> HybridMerkleTree<H> {
+        /// Borrows the on-disk form of the tree, whose `layers` are the stored layers with
+        /// each felt converted by `felt`.
+        pub(super) fn to_raw<'a, F>(
+            &'a self,
+            layers: &'a [Vec<F>],
+            felt: impl Fn(Felt) -> F,
+        ) -> TreeRef<'a, F> {
+            TreeRef {
+                height: self.height,
+                null_base: felt(self.precomputed[0]),
+                free_index: self.free_index,
+                layers,
+                padding: self.padding,
+                sibling_order: self.sibling_order,
+                grafts: &self.grafts,
+                leaf_domain: self.leaf_domain.map(&felt),
+            }
+        }
+    }
+
+    impl<H: MerkleHasher> Serialize for HybridMerkleTree<H> {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            self.to_raw(&self.store.layers, |felt| felt)
+                .serialize(serializer)
+        }
+    }
+
+    /// Leaf range of the root grafted at `index` of `level`, if `add_subtree` can graft one
+    /// there in a tree of `height`.
+    fn graft_range(level: usize, index: usize, height: usize) -> Option<(usize, usize)> {
+        if level == 0 || level >= height {
+            return None;
+        }
+        let leaves = 1usize.checked_shl(level as u32)?;
+        let start = index.checked_mul(leaves)?;
+        Some((start, start.checked_add(leaves)?))
+    }
+
+    impl<H: MerkleHasher> HybridMerkleTree<H> {
+        /// Rebuilds a tree from its on-disk form, checking that the layers match the height,
+        /// leaf count and grafts.
+        pub(super) fn from_raw(raw: TreeOwned<Felt>) -> Result<Self, String> {
+            if raw.height == 0 {
+                return Err(MerkleError::InvalidHeight { height: 0 }.to_string());
+            }
+            if raw.height > CACHED_NULL_HASHES_HEIGHT {
+                return Err(format!(
+                    "height {} is above the limit of {}",
+                    raw.height, CACHED_NULL_HASHES_HEIGHT
+                ));
+            }
+            if raw.layers.len() != raw.height {
+                return Err(format!(
+                    "expected {} layers, found {}",
+                    raw.height,
+                    raw.layers.len()
+                ));
+            }
+            let mut tree = Self::with_hasher_and_null_base(raw.height, raw.null_base);
+            if raw.free_index > tree.capacity() {
+                return Err(MerkleError::TreeFull {
+                    capacity: tree.capacity(),
+                }
+                .to_string());
+            }
+            // Grafts must cover consecutive, aligned leaf ranges, in order, below `free_index`.
+            let mut graft_end = 0;
+            for &(level, root) in &raw.grafts {
+                let range = graft_range(level, root, raw.height)
+                    .filter(|&(start, end)| start >= graft_end && end <= raw.free_index);
+                let Some((_, end)) = range else {
+                    return Err(format!(
+                        "grafted root {} of layer {} is out of place",
+                        root, level
+                    ));
+                };
+                graft_end = end;
+            }
+            for (i, layer) in raw.layers.iter().enumerate() {
+                // Nodes below grafted roots are not stored.
+                let grafted: usize = raw
+                    .grafts
+                    .iter()
+                    .filter(|&&(level, _)| level > i)
+                    .map(|&(level, _)| 1 << (level - i))
+                    .sum();
+                let expected = match raw.free_index {
+                    0 => 0,
+                    n => (n - 1).checked_shr(i as u32).unwrap_or(0) + 1 - grafted,
+                };
+                if layer.len() != expected {
+                    return Err(format!(
+                        "layer {} has {} nodes, expected {}",
+                        i,
+                        layer.len(),
+                        expected
+                    ));
+                }
+            }
+            tree.store.layers = raw.layers;
+            tree.grafts = raw.grafts;
+            tree.padding = raw.padding;
+            tree.sibling_order = raw.sibling_order;
+            tree.leaf_domain = raw.leaf_domain;
+            tree.free_index = raw.free_index;
+            tree.restore_left_path();
+            Ok(tree)
+        }
+    }
+
+    impl<'de, H: MerkleHasher> Deserialize<'de> for HybridMerkleTree<H> {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            Self::from_raw(TreeOwned::deserialize(deserializer)?).map_err(de::Error::custom)
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+mod proof_json {
+    use alloc::string::ToString;
+
+    use serde::{Deserialize, Serialize};
+
+    use super::*;
+
+    /// JSON form of a proof and its root; see `MerkleProof::to_json`.
+    #[derive(Serialize, Deserialize)]
+    #[serde(deny_unknown_fields)]
+    struct ProofJson {
+        leaf: String,
+        index: usize,
+        siblings: Vec<String>,
+        root: String,
+    }
+
+    impl MerkleProof {
+        /// Renders the proof and `root` as JSON, following a stable schema tooling can rely on:
+        /// `{"leaf":"0x..","index":3,"siblings":["0x..",..],"root":"0x.."}`, where Felts are
+        /// `0x`-prefixed 64-char hex strings, `index` is a number and `siblings` starts at the
+        /// leaf level. Side indicators are not included, as they follow from `index`.
+        pub fn to_json(&self, root: &Felt) -> String {
+            let json = ProofJson {
+                leaf: self.leaf.to_fixed_hex_string(),
+                index: self.index.0,
+                siblings: self
+                    .siblings
+                    .iter()
+                    .map(Felt::to_fixed_hex_string)
+                    .collect(),
+                root: root.to_fixed_hex_string(),
+            };
+            serde_json::to_string(&json).expect("Proof JSON always serializes!")
+        }
+
+        /// Parses the output of `to_json` back into the proof and its root. Felts may be any
+        /// value accepted by `parse_felt`. Fails with `InvalidProofJson` if the schema is not
+        /// followed, and with `IndexOutOfRange` if `index` does not fit the siblings.
+        pub fn from_json(json: &str) -> Result<(Self, Felt), MerkleError> {
+            let json: ProofJson =
+                serde_json::from_str(json).map_err(|e| MerkleError::InvalidProofJson {
+                    reason: e.to_string(),
+                })?;
+            let levels = json.siblings.len();
+            if json.index.checked_shr(levels as u32).unwrap_or(0) != 0 {
+                return Err(MerkleError::IndexOutOfRange {
+                    index: json.index,
+                    capacity: 1usize.checked_shl(levels as u32).unwrap_or(usize::MAX),
+                });
+            }
+            let proof = MerkleProof {
+                leaf: parse_felt(&json.leaf)?,
+                index: LeafIndex(json.index),
+                siblings: json
+                    .siblings
+                    .iter()
+                    .map(|sibling| parse_felt(sibling))
+                    .collect::<Result<_, _>>()?,
+                is_right: bits_from_index(json.index, levels + 1),
+            };
+            Ok((proof, parse_felt(&json.root)?))
+        }
+    }
+}
+
+#[cfg(all(feature = "serde", feature = "std"))]
+use serde_impl::{LittleEndianFelt, TreeOwned};
+
+#[cfg(all(feature = "serde", feature = "std"))]
+impl HybridMerkleTree {
+    /// Reads a tree written by `save_to_path`. Fails with `InvalidData` if the file does not
+    /// decode, or if its layers do not match the stored height and leaf count.
+    pub fn load_from_path(path: &std::path::Path) -> std::io::Result<Self> {
+        Self::load_from_path_with_hasher(path)
+    }
+
+    /// Reads a tree written by `save_to_path_with` with the same `endianness`.
+    pub fn load_from_path_with(
+        path: &std::path::Path,
+        endianness: Endianness,
+    ) -> std::io::Result<Self> {
+        Self::load_from_path_with_hasher_and_endianness(path, endianness)
+    }
+}
+
+#[cfg(all(feature = "serde", feature = "std"))]
+impl<H: MerkleHasher> HybridMerkleTree<H> {
+    /// Writes the tree to `path` in bincode's compact binary encoding, with big-endian felts.
+    pub fn save_to_path(&self, path: &std::path::Path) -> std::io::Result<()> {
+        self.save_to_path_with(path, Endianness::Big)
+    }
+
+    /// Same as `save_to_path`, writing each felt's 32 bytes in `endianness` order. Lengths and
+    /// indices keep bincode's standard variable-length encoding.
+    pub fn save_to_path_with(
+        &self,
+        path: &std::path::Path,
+        endianness: Endianness,
+    ) -> std::io::Result<()> {
+        let config = bincode::config::standard();
+        let bytes = match endianness {
+            Endianness::Big => bincode::serde::encode_to_vec(self, config),
+            Endianness::Little => {
+                let layers: Vec<Vec<_>> = self
+                    .store
+                    .layers
+                    .iter()
+                    .map(|layer| layer.iter().map(|&felt| LittleEndianFelt(felt)).collect())
+                    .collect();
+                bincode::serde::encode_to_vec(self.to_raw(&layers, LittleEndianFelt), config)
+            }
+        }
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+        std::fs::write(path, bytes)
+    }
+
+    /// Same as `load_from_path`, for a tree combining nodes with `H`.
+    pub fn load_from_path_with_hasher(path: &std::path::Path) -> std::io::Result<Self> {
+        Self::load_from_path_with_hasher_and_endianness(path, Endianness::Big)
+    }
+
+    /// Same as `load_from_path_with`, for a tree combining nodes with `H`.
+    pub fn load_from_path_with_hasher_and_endianness(
+        path: &std::path::Path,
+        endianness: Endianness,
+    ) -> std::io::Result<Self> {
+        let invalid = |e| std::io::Error::new(std::io::ErrorKind::InvalidData, e);
+        let bytes = std::fs::read(path)?;
+        let config = bincode::config::standard();
+        let (tree, read) = match endianness {
+            Endianness::Big => bincode::serde::decode_from_slice(&bytes, config)
+                .map_err(|e| invalid(e.to_string()))?,
+            Endianness::Little => {
+                let (raw, read): (TreeOwned<LittleEndianFelt>, _) =
+                    bincode::serde::decode_from_slice(&bytes, config)
+                        .map_err(|e| invalid(e.to_string()))?;
+                (
+                    Self::from_raw(raw.map_felts(|LittleEndianFelt(felt)| felt))
+                        .map_err(invalid)?,
+                    read,
+                )
+            }
+        };
+        if read != bytes.len() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("{} trailing bytes after the tree", bytes.len() - read),
+            ));
+        }
+        Ok(tree)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::helpers::{
+        compute_and_verify, compute_merkle_root_rust, compute_merkle_root_with_domain,
+        compute_root_from_bits, hash_leaf_data, hash_null_levels, hash_sorted, index_from_bits,
+        parse_felt, precomputed_hashes, proof_cost, verify_batch, verify_compressed,
+        verify_compressed_with_null_base, verify_consistency, verify_multi_proof, verify_proof,
+        verify_range_proof, NULL_HASHES,
+    };
+
+    impl<H: MerkleHasher, S: NodeStore> HybridMerkleTree<H, S> {
+        /// Overwrites `left_path[level]`, breaking the invariant `check_invariants` enforces.
+        fn corrupt_left_path(&mut self, level: usize) {
+            self.left_path[level] += Felt::ONE;
+        }
+    }
+
+    #[test]
+    fn test_hybrid_merkle_tree_root() {
+        let mut tree = HybridMerkleTree::new(3); // 3 levels for 4 leaves
+        let leaf0 = Felt::from(1);
+        let leaf1 = Felt::from(2);
+        let leaf2 = Felt::from(3);
+        let leaf3 = Felt::from(4);
+        tree.add_leaf(&leaf0).unwrap();
+        tree.add_leaf(&leaf1).unwrap();
+        tree.add_leaf(&leaf2).unwrap();
+        tree.add_leaf(&leaf3).unwrap();
+
+        let root = tree.root();
+        assert_ne!(
+            root,
+            precomputed_hashes::<PoseidonHasher>(3, default_null_base())[2],
+            "Root should not be the default null value"
+        );
+    }
+
+    #[test]
+    fn test_compute_merkle_root_rust_simulation() {
+        let height = 3;
+        let mut tree = HybridMerkleTree::new(height);
+        let leaf0 = Felt::from(1);
+        let leaf1 = Felt::from(2);
+        let leaf2 = Felt::from(3);
+        let leaf3 = Felt::from(4);
+        tree.add_leaf(&leaf0).unwrap();
+        tree.add_leaf(&leaf1).unwrap();
+        tree.add_leaf(&leaf2).unwrap();
+        tree.add_leaf(&leaf3).unwrap();
+
+        let index = 0usize;
+        let (proof, _bits) = tree.path(LeafIndex(index)).unwrap();
+        let computed_root = compute_merkle_root_rust::<PoseidonHasher>(leaf0, index, &proof);
+        let tree_root = tree.root();
+        assert_eq!(
+            computed_root, tree_root,
+            "Computed root from proof does not match tree root"
+        );
+    }
+
+    #[test]
+    fn test_root_with_poseidon_matches_root() {
+        use core::cell::Cell;
+        use starknet_types_core::hash::{Poseidon, StarkHash};
+
+        let leaves: Vec<Felt> = (1..=5u32).map(Felt::from).collect();
+        for (padding, order) in [
+            (Padding::Right, SiblingOrder::Positional),
+            (Padding::Left, SiblingOrder::Positional),
+            (Padding::Right, SiblingOrder::Sorted),
+        ] {
+            let mut tree = HybridMerkleTree::builder()
+                .height(4)
+                .padding(padding)
+                .sibling_order(order)
+                .build();
+            assert_eq!(tree.root_with(Poseidon::hash), tree.root());
+            tree.add_leaves(&leaves).unwrap();
+            assert_eq!(tree.root_with(Poseidon::hash), tree.root());
+        }
+
+        let tree = HybridMerkleTree::from_leaves(4, &leaves).unwrap();
+        let calls = Cell::new(0);
+        let counted = tree.root_with(|a, b| {
+            calls.set(calls.get() + 1);
+            Poseidon::hash(a, b)
+        });
+        assert_eq!(counted, tree.root());
+        // 3 + 2 + 1 nodes, and one null per level above the leaves.
+        assert_eq!(calls.get(), 9);
+        assert_ne!(tree.root_with(|a, b| a + b), tree.root());
+    }
+
+    #[test]
+    fn test_high_depth_tree_memory() {
+        let height = 32;
+        let mut tree = HybridMerkleTree::new(height);
+        let num_leaves = 100;
+        for i in 0..num_leaves {
+            tree.add_leaf(&Felt::from(i as u32)).unwrap();
+        }
+        assert_eq!(tree.len(), num_leaves);
+        for (i, layer) in tree.store.layers.iter().enumerate() {
+            let expected_max = ((num_leaves as f64) / (2.0f64.powi(i as i32))).ceil() as usize;
+            assert!(
+                layer.len() <= expected_max,
+                "Layer {} has {} elements, expected at most {}",
+                i,
+                layer.len(),
+                expected_max
+            );
+        }
+        assert_ne!(
+            tree.root(),
+            tree.precomputed[height - 1],
+            "Root should not be the default null value"
+        );
+    }
+
+    #[test]
+    fn test_compute_merkle_root_high_depth() {
+        let height = 32;
+        let mut tree = HybridMerkleTree::new(height);
+        let leaves: Vec<Felt> = (0..10).map(|i| Felt::from(i as u32 + 1)).collect();
+        for leaf in &leaves {
+            tree.add_leaf(leaf).unwrap();
+        }
+        let index = 0usize;
+        let (proof, _bits) = tree.path(LeafIndex(index)).unwrap();
+        let computed_root = compute_merkle_root_rust::<PoseidonHasher>(leaves[0], index, &proof);
+        let tree_root = tree.root();
+        assert_eq!(
+            computed_root, tree_root,
+            "Computed root from proof does not match tree root for high depth tree"
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Leaf does not exist!")]
+    fn test_path_for_nonexistent_leaf() {
+        let tree = HybridMerkleTree::new(3);
+        let _ = tree.path_unchecked(LeafIndex(0));
+    }
+
+    #[test]
+    fn test_add_leaf_returns_assigned_index() {
+        let mut tree = HybridMerkleTree::new(3);
+        assert_eq!(tree.add_leaf(&Felt::from(1)), Ok(0));
+        assert_eq!(tree.add_leaf(&Felt::from(2)), Ok(1));
+    }
+
+    #[test]
+    fn test_add_leaf_rejects_leaves_beyond_capacity() {
+        let mut tree = HybridMerkleTree::new(3);
+        assert_eq!(tree.capacity(), 4);
+        for i in 0..4 {
+            assert!(tree.add_leaf(&Felt::from(i as u32)).is_ok());
+        }
+        let root = tree.root();
+        assert_eq!(
+            tree.add_leaf(&Felt::from(4)),
+            Err(MerkleError::TreeFull { capacity: 4 })
+        );
+        assert_eq!(tree.root(), root, "A rejected leaf must not alter the tree");
+    }
+
+    #[test]
+    fn test_index_beyond_capacity_is_out_of_range() {
+        let mut tree = HybridMerkleTree::new(3);
+        tree.add_leaf(&Felt::from(1)).unwrap();
+        assert_eq!(
+            tree.path(LeafIndex(4)),
+            Err(MerkleError::IndexOutOfRange {
+                index: 4,
+                capacity: 4
+            })
+        );
+        assert_eq!(
+            tree.update_leaf(LeafIndex(2), &Felt::from(2)),
+            Err(MerkleError::LeafNotFound { index: 2, len: 1 })
+        );
+    }
+
+    #[test]
+    fn test_path_returns_error_for_nonexistent_leaf() {
+        let mut tree = HybridMerkleTree::new(3);
+        tree.add_leaf(&Felt::from(1)).unwrap();
+        assert_eq!(
+            tree.path(LeafIndex(1)),
+            Err(MerkleError::LeafNotFound { index: 1, len: 1 })
+        );
+    }
+
+    #[test]
+    fn test_path_with_nodes_folds_to_root() {
+        let mut tree = HybridMerkleTree::new(4);
+        tree.add_leaves(&[1u32, 2, 3, 4, 5].map(Felt::from))
+            .unwrap();
+        let levels = tree.path_with_nodes(LeafIndex(2)).unwrap();
+        assert_eq!(levels.len(), 3);
+        assert_eq!(levels[0].0, Felt::from(3));
+        let mut current = levels[0].0;
+        for &(node, sibling, is_right) in &levels {
+            assert_eq!(node, current);
+            current = if is_right {
+                PoseidonHasher::hash(&sibling, &node)
+            } else {
+                PoseidonHasher::hash(&node, &sibling)
+            };
+        }
+        assert_eq!(current, tree.root());
+        assert_eq!(
+            tree.path_with_nodes(LeafIndex(5)),
+            Err(MerkleError::LeafNotFound { index: 5, len: 5 })
+        );
+
+        let tall = HybridMerkleTree::from_leaves(70, &[Felt::ONE, Felt::TWO]).unwrap();
+        let levels = tall.path_with_nodes(LeafIndex(1)).unwrap();
+        assert_eq!(levels[68].0, tall.store.get(68, 0).unwrap());
+    }
+
+    #[test]
+    fn test_boundary_proofs_of_partial_trees() {
+        // Missing right neighbours are empty subtrees, so their null hash is the right sibling.
+        // Check every proof against a naive reduction of the fully padded leaf level.
+        for (height, count) in [(3, 3), (4, 3), (4, 5), (5, 5)] {
+            let leaves: Vec<Felt> = (1..=count).map(|i| Felt::from(i as u32)).collect();
+            let tree = HybridMerkleTree::from_leaves(height, &leaves).unwrap();
+            let mut level = leaves.clone();
+            level.resize(1 << (height - 1), default_null_base());
+            let mut levels = vec![level];
+            while levels.last().unwrap().len() > 1 {
+                let below = levels.last().unwrap();
+                levels.push(
+                    below
+                        .chunks(2)
+                        .map(|pair| PoseidonHasher::hash(&pair[0], &pair[1]))
+                        .collect(),
+                );
+            }
+            assert_eq!(tree.root(), levels[height - 1][0]);
+            for index in 0..count {
+                let (siblings, _) = tree.path(LeafIndex(index)).unwrap();
+                let expected: Vec<Felt> = (0..height - 1)
+                    .map(|l| levels[l][(index >> l) ^ 1])
+                    .collect();
+                assert_eq!(siblings, expected, "leaf {} of {}", index, count);
+                assert!(tree.proof(LeafIndex(index)).unwrap().verify(&tree.root()));
+            }
+        }
+    }
+
+    #[cfg(debug_assertions)]
+    #[test]
+    #[should_panic(expected = "left_path[1]")]
+    fn test_check_invariants_catches_stale_left_path() {
+        let mut tree = HybridMerkleTree::new(4);
+        tree.add_leaves(&[1u32, 2, 3].map(Felt::from)).unwrap();
+        tree.corrupt_left_path(1);
+        tree.add_leaf(&Felt::from(4)).unwrap();
+    }
+
+    #[test]
+    fn test_restore_left_path_above_64_levels() {
+        let leaves = [1u32, 2, 3].map(Felt::from);
+        let mut tree = HybridMerkleTree::from_leaves(65, &leaves).unwrap();
+        let mut expected = HybridMerkleTree::new(65);
+        expected.add_leaves(&leaves).unwrap();
+        assert_eq!(tree.root(), expected.root());
+        tree.rehash();
+        assert_eq!(tree.root(), expected.root());
+        tree.truncate(2).unwrap();
+        expected = HybridMerkleTree::new(65);
+        expected.add_leaves(&leaves[..2]).unwrap();
+        assert_eq!(tree.root(), expected.root());
+    }
+
+    #[test]
+    fn test_add_leaf_above_64_levels() {
+        let mut tree = HybridMerkleTree::new(70);
+        tree.add_leaf(&Felt::ONE).unwrap();
+        tree.add_leaf(&Felt::TWO).unwrap();
+        assert!(tree.proof(LeafIndex(1)).unwrap().verify(&tree.root()));
+
+        tree.update_leaf(LeafIndex(1), &Felt::THREE).unwrap();
+        let mut expected = HybridMerkleTree::new(70);
+        expected.add_leaves(&[Felt::ONE, Felt::THREE]).unwrap();
+        assert_eq!(tree.root(), expected.root());
+    }
+
+    #[test]
+    fn test_path_errors_on_short_layer() {
+        let mut tree = HybridMerkleTree::new(4);
+        tree.add_leaves(&[1u32, 2, 3, 4, 5].map(Felt::from))
+            .unwrap();
+        // Drop the tail of level 1, as a store losing nodes would.
+        tree.store.layers[1].truncate(1);
+        assert_eq!(
+            tree.path(LeafIndex(0)),
+            Err(MerkleError::PathUnavailable { index: 0 })
+        );
+        assert_eq!(
+            tree.path(LeafIndex(1)),
+            Err(MerkleError::PathUnavailable { index: 1 })
+        );
+        // The dropped nodes are not on these paths.
+        for index in 2..5 {
+            assert!(tree.proof(LeafIndex(index)).unwrap().verify(&tree.root()));
+        }
+    }
+
+    #[test]
+    fn test_single_leaf_tree() {
+        let mut tree = HybridMerkleTree::new(3);
+        let leaf = Felt::from(42);
+        tree.add_leaf(&leaf).unwrap();
+        let root = tree.root();
+        assert_ne!(
+            root, tree.precomputed[2],
+            "Root for single leaf should not be the default null value"
+        );
+    }
+
+    #[test]
+    fn test_path_consistency() {
+        let mut tree = HybridMerkleTree::new(3);
+        let leaf0 = Felt::from(1);
+        let leaf1 = Felt::from(2);
+        tree.add_leaf(&leaf0).unwrap();
+        tree.add_leaf(&leaf1).unwrap();
+        let (proof0, bits0) = tree.path(LeafIndex(0)).unwrap();
+        let (proof1, bits1) = tree.path(LeafIndex(1)).unwrap();
+        assert_ne!(proof0, proof1, "Proofs for different leaves should differ");
+        assert_ne!(
+            bits0, bits1,
             "Proof bit patterns for different leaves should differ"
         );
     }
+
+    #[test]
+    fn test_verify_proof_round_trip() {
+        let mut tree = HybridMerkleTree::new(3);
+        let leaves: Vec<Felt> = (1..=3).map(|i| Felt::from(i as u32)).collect();
+        for leaf in &leaves {
+            tree.add_leaf(leaf).unwrap();
+        }
+        let root = tree.root();
+        for (index, leaf) in leaves.iter().enumerate() {
+            let (proof, _bits) = tree.path(LeafIndex(index)).unwrap();
+            assert_eq!(
+                verify_proof::<PoseidonHasher>(&root, leaf, index, &proof, 3),
+                Ok(true)
+            );
+            assert_eq!(
+                verify_proof::<PoseidonHasher>(&root, leaf, index ^ 1, &proof, 3),
+                Ok(false)
+            );
+        }
+    }
+
+    #[test]
+    fn test_verify_proof_rejects_malformed_proofs() {
+        let mut tree = HybridMerkleTree::new(3);
+        tree.add_leaf(&Felt::from(1)).unwrap();
+        let root = tree.root();
+        let (proof, _bits) = tree.path(LeafIndex(0)).unwrap();
+        assert_eq!(
+            verify_proof::<PoseidonHasher>(&root, &Felt::from(1), 4, &proof, 3),
+            Err(MerkleError::IndexOutOfRange {
+                index: 4,
+                capacity: 4
+            })
+        );
+        assert_eq!(
+            verify_proof::<PoseidonHasher>(&root, &Felt::from(1), 0, &proof[..1], 3),
+            Err(MerkleError::ProofLengthMismatch {
+                expected: 2,
+                actual: 1
+            })
+        );
+        assert_eq!(
+            verify_proof::<PoseidonHasher>(&root, &Felt::from(1), 0, &proof, 4),
+            Err(MerkleError::ProofLengthMismatch {
+                expected: 3,
+                actual: 2
+            })
+        );
+        assert_eq!(
+            verify_proof::<PoseidonHasher>(&root, &Felt::from(1), 0, &[], 1),
+            Ok(false)
+        );
+    }
+
+    #[test]
+    fn test_compressed_proof_omits_null_siblings() {
+        let mut tree = HybridMerkleTree::new(32);
+        tree.add_leaf(&Felt::from(1)).unwrap();
+        let compressed = tree.compressed_path(LeafIndex(0)).unwrap();
+        assert_eq!(compressed.siblings.len(), 31);
+        assert!(compressed.siblings.iter().all(Option::is_none));
+        assert_eq!(
+            verify_compressed::<PoseidonHasher>(&tree.root(), &Felt::from(1), 0, &compressed, 32),
+            Ok(true)
+        );
+        assert_eq!(
+            verify_compressed::<PoseidonHasher>(&tree.root(), &Felt::from(2), 0, &compressed, 32),
+            Ok(false)
+        );
+
+        // Only the levels holding other leaves keep their sibling.
+        tree.add_leaves(&[2u32, 3, 4, 5].map(Felt::from)).unwrap();
+        let compressed = tree.compressed_path(LeafIndex(4)).unwrap();
+        let explicit: Vec<usize> = (0..31)
+            .filter(|&l| compressed.siblings[l].is_some())
+            .collect();
+        assert_eq!(explicit, vec![2]);
+        let (siblings, _) = tree.path(LeafIndex(4)).unwrap();
+        assert_eq!(compressed.siblings[2], Some(siblings[2]));
+        assert_eq!(
+            verify_compressed::<PoseidonHasher>(&tree.root(), &Felt::from(5), 4, &compressed, 32),
+            Ok(true)
+        );
+        assert_eq!(
+            verify_compressed::<PoseidonHasher>(&tree.root(), &Felt::from(5), 4, &compressed, 31),
+            Err(MerkleError::ProofLengthMismatch {
+                expected: 30,
+                actual: 31
+            })
+        );
+
+        let mut zero_based = HybridMerkleTree::with_null_base(8, Felt::ZERO);
+        zero_based.add_leaves(&[Felt::ONE, Felt::TWO]).unwrap();
+        let compressed = zero_based.compressed_path(LeafIndex(1)).unwrap();
+        let root = zero_based.root();
+        assert_eq!(
+            verify_compressed_with_null_base::<PoseidonHasher>(
+                &root,
+                &Felt::TWO,
+                1,
+                &compressed,
+                8,
+                Felt::ZERO
+            ),
+            Ok(true)
+        );
+        assert_eq!(
+            verify_compressed::<PoseidonHasher>(&root, &Felt::TWO, 1, &compressed, 8),
+            Ok(false)
+        );
+    }
+
+    #[test]
+    fn test_merkle_proof_verify() {
+        let mut tree = HybridMerkleTree::new(3);
+        for i in 1..=3 {
+            tree.add_leaf(&Felt::from(i as u32)).unwrap();
+        }
+        let root = tree.root();
+        let mut proof = tree.proof(LeafIndex(2)).unwrap();
+        assert_eq!(proof.leaf, Felt::from(3));
+        assert!(proof.verify(&root));
+
+        proof.is_right[0] = !proof.is_right[0];
+        assert!(
+            !proof.verify(&root),
+            "Side indicators that contradict the index must be rejected"
+        );
+    }
+
+    #[test]
+    fn test_update_leaf_matches_rebuilt_tree() {
+        let mut tree = HybridMerkleTree::new(3);
+        for i in 1..=4 {
+            tree.add_leaf(&Felt::from(i as u32)).unwrap();
+        }
+        tree.update_leaf(LeafIndex(1), &Felt::from(20)).unwrap();
+
+        let mut rebuilt = HybridMerkleTree::new(3);
+        for leaf in [1u32, 20, 3, 4] {
+            rebuilt.add_leaf(&Felt::from(leaf)).unwrap();
+        }
+        assert_eq!(tree.root(), rebuilt.root());
+        assert_eq!(
+            tree.path(LeafIndex(0)).unwrap(),
+            rebuilt.path(LeafIndex(0)).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_update_leaf_keeps_appending_consistent() {
+        let mut tree = HybridMerkleTree::new(3);
+        for i in 1..=3 {
+            tree.add_leaf(&Felt::from(i as u32)).unwrap();
+        }
+        tree.update_leaf(LeafIndex(2), &Felt::from(30)).unwrap();
+        tree.add_leaf(&Felt::from(4)).unwrap();
+
+        let mut rebuilt = HybridMerkleTree::new(3);
+        for leaf in [1u32, 2, 30, 4] {
+            rebuilt.add_leaf(&Felt::from(leaf)).unwrap();
+        }
+        assert_eq!(tree.root(), rebuilt.root());
+    }
+
+    #[cfg(target_pointer_width = "64")]
+    #[test]
+    fn test_compute_merkle_root_high_index() {
+        let height = 40;
+        let hash_path = precomputed_hashes::<PoseidonHasher>(height, default_null_base())
+            [..height - 1]
+            .to_vec();
+        let leaf = Felt::from(7);
+        let index = (1usize << 35) | 1;
+        let root = compute_merkle_root_rust::<PoseidonHasher>(leaf, index, &hash_path);
+        assert_ne!(
+            root,
+            compute_merkle_root_rust::<PoseidonHasher>(leaf, index as u32 as usize, &hash_path),
+            "High index bits must not be truncated"
+        );
+        assert_eq!(
+            verify_proof::<PoseidonHasher>(&root, &leaf, index, &hash_path, height),
+            Ok(true)
+        );
+        assert_eq!(
+            verify_proof::<PoseidonHasher>(&root, &leaf, 1, &hash_path, height),
+            Ok(false)
+        );
+    }
+
+    #[test]
+    fn test_from_leaves_matches_incremental_tree() {
+        let leaves: Vec<Felt> = (1..=5).map(|i| Felt::from(i as u32)).collect();
+        let mut incremental = HybridMerkleTree::new(4);
+        for leaf in &leaves {
+            incremental.add_leaf(leaf).unwrap();
+        }
+        let mut bulk = HybridMerkleTree::from_leaves(4, &leaves).unwrap();
+        assert_eq!(bulk, incremental);
+        assert_eq!(bulk.store.layers, incremental.store.layers);
+        for index in 0..leaves.len() {
+            assert_eq!(
+                bulk.path(LeafIndex(index)),
+                incremental.path(LeafIndex(index))
+            );
+        }
+
+        // The left path must also match so that appends keep working.
+        bulk.add_leaf(&Felt::from(6)).unwrap();
+        incremental.add_leaf(&Felt::from(6)).unwrap();
+        assert_eq!(bulk.root(), incremental.root());
+    }
+
+    #[test]
+    fn test_from_leaves_rejects_too_many_leaves() {
+        let leaves: Vec<Felt> = (0..5).map(|i| Felt::from(i as u32)).collect();
+        assert_eq!(
+            HybridMerkleTree::from_leaves(3, &leaves).unwrap_err(),
+            MerkleError::TreeFull { capacity: 4 }
+        );
+    }
+
+    #[test]
+    fn test_with_null_base() {
+        let tree = HybridMerkleTree::with_null_base(3, Felt::ZERO);
+        let level1 = PoseidonHasher::hash(&Felt::ZERO, &Felt::ZERO);
+        assert_eq!(tree.root(), PoseidonHasher::hash(&level1, &level1));
+        assert_eq!(
+            HybridMerkleTree::new(3).root(),
+            HybridMerkleTree::with_null_base(3, default_null_base()).root()
+        );
+        assert_ne!(tree.root(), HybridMerkleTree::new(3).root());
+    }
+
+    #[test]
+    fn test_len_is_empty_and_height() {
+        let mut tree = HybridMerkleTree::new(3);
+        assert!(tree.is_empty());
+        assert_eq!(tree.len(), 0);
+        assert_eq!(tree.height(), 3);
+        tree.add_leaf(&Felt::from(1)).unwrap();
+        tree.add_leaf(&Felt::from(2)).unwrap();
+        assert!(!tree.is_empty());
+        assert_eq!(tree.len(), 2);
+    }
+
+    #[test]
+    fn test_get_leaf() {
+        let mut tree = HybridMerkleTree::new(3);
+        tree.add_leaf(&Felt::from(10)).unwrap();
+        tree.add_leaf(&Felt::from(20)).unwrap();
+        assert_eq!(tree.get_leaf(LeafIndex(1)), Some(Felt::from(20)));
+        assert_eq!(tree.get_leaf(LeafIndex(2)), None);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_round_trip() {
+        let mut tree = HybridMerkleTree::with_null_base(4, Felt::ZERO);
+        for i in 1..=5 {
+            tree.add_leaf(&Felt::from(i as u32)).unwrap();
+        }
+        let json = serde_json::to_string(&tree).unwrap();
+        assert!(
+            json.contains("\"0x5\""),
+            "Felts should serialize as hex strings"
+        );
+
+        let mut restored: HybridMerkleTree = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored.root(), tree.root());
+        assert_eq!(restored.path(LeafIndex(2)), tree.path(LeafIndex(2)));
+        restored.add_leaf(&Felt::from(6)).unwrap();
+        tree.add_leaf(&Felt::from(6)).unwrap();
+        assert_eq!(restored.root(), tree.root());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_keeps_leaf_domain() {
+        let mut tree = HybridMerkleTree::new(4).with_leaf_domain(Felt::from(9));
+        tree.add_leaves(&[Felt::ONE, Felt::TWO]).unwrap();
+        let mut restored: HybridMerkleTree =
+            serde_json::from_str(&serde_json::to_string(&tree).unwrap()).unwrap();
+        restored.add_leaf(&Felt::THREE).unwrap();
+        tree.add_leaf(&Felt::THREE).unwrap();
+        assert_eq!(restored.root(), tree.root());
+
+        // Payloads from before the leaf domain was stored load without one.
+        let mut value = serde_json::to_value(HybridMerkleTree::new(4)).unwrap();
+        value.as_object_mut().unwrap().remove("leaf_domain");
+        let restored: HybridMerkleTree = serde_json::from_value(value).unwrap();
+        assert_eq!(restored, HybridMerkleTree::new(4));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_rejects_inconsistent_layers() {
+        let mut tree = HybridMerkleTree::new(3);
+        tree.add_leaf(&Felt::from(1)).unwrap();
+        let mut value = serde_json::to_value(&tree).unwrap();
+        value["free_index"] = serde_json::json!(2);
+        assert!(serde_json::from_value::<HybridMerkleTree>(value).is_err());
+
+        for height in [0, 257] {
+            let mut value = serde_json::to_value(HybridMerkleTree::new(3)).unwrap();
+            value["height"] = serde_json::json!(height);
+            value["layers"] = serde_json::json!(vec![Vec::<Felt>::new(); height]);
+            assert!(serde_json::from_value::<HybridMerkleTree>(value).is_err());
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_validates_grafts() {
+        let mut tree = HybridMerkleTree::new(4);
+        tree.add_subtree(&Felt::from(9), 1).unwrap();
+        tree.add_leaves(&[Felt::ONE, Felt::TWO]).unwrap();
+        let value = serde_json::to_value(&tree).unwrap();
+        let restored: HybridMerkleTree = serde_json::from_value(value.clone()).unwrap();
+        assert_eq!(restored.root(), tree.root());
+        assert_eq!(restored.get_leaf(LeafIndex(2)), Some(Felt::ONE));
+
+        let mut overflowing = value.clone();
+        overflowing["grafts"] = serde_json::json!([[1, u64::MAX]]);
+        assert!(serde_json::from_value::<HybridMerkleTree>(overflowing).is_err());
+        let mut unordered = value;
+        unordered["grafts"] = serde_json::json!([[1, 1], [1, 0]]);
+        assert!(serde_json::from_value::<HybridMerkleTree>(unordered).is_err());
+
+        // A graft over stored leaves does not match the layers.
+        let plain = HybridMerkleTree::from_leaves(4, &[Felt::ONE, Felt::TWO]).unwrap();
+        let mut value = serde_json::to_value(&plain).unwrap();
+        value["grafts"] = serde_json::json!([[1, 0]]);
+        assert!(serde_json::from_value::<HybridMerkleTree>(value).is_err());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_round_trip_above_64_levels() {
+        let tree = HybridMerkleTree::from_leaves(65, &[Felt::ONE, Felt::TWO]).unwrap();
+        let json = serde_json::to_string(&tree).unwrap();
+        let restored: HybridMerkleTree = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored, tree);
+    }
+
+    #[cfg(all(feature = "serde", feature = "std"))]
+    #[test]
+    fn test_save_and_load_round_trip() {
+        let mut tree = HybridMerkleTree::new(4);
+        tree.add_leaves(&[Felt::from(1), Felt::from(2), Felt::from(3)])
+            .unwrap();
+        let file = tempfile::NamedTempFile::new().unwrap();
+        tree.save_to_path(file.path()).unwrap();
+
+        let restored = HybridMerkleTree::load_from_path(file.path()).unwrap();
+        assert_eq!(restored.root(), tree.root());
+        assert_eq!(restored, tree);
+
+        let mut tagged = HybridMerkleTree::new(4).with_leaf_domain(Felt::from(9));
+        tagged.add_leaf(&Felt::ONE).unwrap();
+        tagged.save_to_path(file.path()).unwrap();
+        let mut restored = HybridMerkleTree::load_from_path(file.path()).unwrap();
+        restored.add_leaf(&Felt::TWO).unwrap();
+        tagged.add_leaf(&Felt::TWO).unwrap();
+        assert_eq!(restored.root(), tagged.root());
+    }
+
+    #[cfg(all(feature = "serde", feature = "std"))]
+    #[test]
+    fn test_save_and_load_with_endianness() {
+        let mut tree = HybridMerkleTree::new(4).with_leaf_domain(Felt::from(9));
+        tree.add_leaves(&[Felt::from(1), Felt::from(2), Felt::from(3)])
+            .unwrap();
+        let file = tempfile::NamedTempFile::new().unwrap();
+        tree.save_to_path(file.path()).unwrap();
+        let default_bytes = std::fs::read(file.path()).unwrap();
+
+        for endianness in [Endianness::Big, Endianness::Little] {
+            tree.save_to_path_with(file.path(), endianness).unwrap();
+            let bytes = std::fs::read(file.path()).unwrap();
+            assert_eq!(bytes == default_bytes, endianness == Endianness::Big);
+            let restored = HybridMerkleTree::load_from_path_with(file.path(), endianness).unwrap();
+            assert_eq!(restored, tree);
+        }
+
+        // The little-endian file holds the null base least significant byte first.
+        let bytes = std::fs::read(file.path()).unwrap();
+        assert!(bytes
+            .windows(32)
+            .any(|window| window == default_null_base().to_bytes_le()));
+    }
+
+    #[cfg(all(feature = "serde", feature = "std"))]
+    #[test]
+    fn test_load_rejects_inconsistent_file() {
+        let mut tree = HybridMerkleTree::new(3);
+        tree.add_leaf(&Felt::from(1)).unwrap();
+        let file = tempfile::NamedTempFile::new().unwrap();
+        tree.save_to_path(file.path()).unwrap();
+
+        // The height is the first field, encoded as a single varint byte.
+        let mut bytes = std::fs::read(file.path()).unwrap();
+        bytes[0] = 4;
+        std::fs::write(file.path(), &bytes).unwrap();
+        let err = HybridMerkleTree::load_from_path(file.path()).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+
+        bytes[0] = 3;
+        bytes.push(0);
+        std::fs::write(file.path(), &bytes).unwrap();
+        let err = HybridMerkleTree::load_from_path(file.path()).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_to_prover_toml() {
+        let mut tree = HybridMerkleTree::new(3);
+        tree.add_leaf(&Felt::from(1)).unwrap();
+        tree.add_leaf(&Felt::from(42)).unwrap();
+        let proof = tree.proof(LeafIndex(1)).unwrap();
+        let toml = proof.to_prover_toml(&tree.root());
+        let lines: Vec<&str> = toml.lines().collect();
+        assert_eq!(lines[0], format!("leaf = \"0x{:0>64}\"", "2a"));
+        assert_eq!(lines[1], "index = 1");
+        assert_eq!(
+            lines[2],
+            format!(
+                "hash_path = [\"{}\", \"{}\"]",
+                proof.siblings[0].to_fixed_hex_string(),
+                proof.siblings[1].to_fixed_hex_string()
+            )
+        );
+        assert_eq!(
+            lines[3],
+            format!("root = \"{}\"", tree.root().to_fixed_hex_string())
+        );
+    }
+
+    #[test]
+    fn test_validate_shape() {
+        let mut tree = HybridMerkleTree::new(4);
+        tree.add_leaves(&[Felt::ZERO, Felt::ONE, Felt::TWO])
+            .unwrap();
+        let proof = tree.proof(LeafIndex(1)).unwrap();
+        assert_eq!(proof.validate_shape(4), Ok(()));
+        // A zero leaf is a legitimate level-0 sibling.
+        assert_eq!(proof.siblings[0], Felt::ZERO);
+
+        assert_eq!(
+            proof.validate_shape(5),
+            Err(MerkleError::ProofLengthMismatch {
+                expected: 4,
+                actual: 3
+            })
+        );
+        let mut truncated = proof.clone();
+        truncated.siblings.pop();
+        assert_eq!(
+            truncated.validate_shape(4),
+            Err(MerkleError::ProofLengthMismatch {
+                expected: 3,
+                actual: 2
+            })
+        );
+
+        let mut zeroed = proof.clone();
+        zeroed.siblings[2] = Felt::ZERO;
+        assert_eq!(
+            zeroed.validate_shape(4),
+            Err(MerkleError::MalformedProof { level: 2 })
+        );
+        let mut flipped = proof.clone();
+        flipped.is_right[1] = true;
+        assert_eq!(
+            flipped.validate_shape(4),
+            Err(MerkleError::MalformedProof { level: 1 })
+        );
+        let mut moved = proof;
+        moved.index = LeafIndex(8);
+        assert_eq!(
+            moved.validate_shape(4),
+            Err(MerkleError::IndexOutOfRange {
+                index: 8,
+                capacity: 8
+            })
+        );
+    }
+
+    #[test]
+    fn test_describe_lists_each_level() {
+        let mut tree = HybridMerkleTree::new(4);
+        tree.add_leaves(&[Felt::from(1), Felt::from(2)]).unwrap();
+        let proof = tree.proof(LeafIndex(1)).unwrap();
+        let description = proof.describe();
+        let levels: Vec<&str> = description.lines().skip(1).collect();
+        assert_eq!(levels.len(), 3);
+        assert_eq!(
+            levels[0],
+            format!(
+                "L0: sibling={}, side=right",
+                Felt::from(1).to_fixed_hex_string()
+            )
+        );
+        assert!(levels[1].starts_with("L1: ") && levels[1].ends_with("side=left"));
+    }
+
+    /// Adds the two children, so every node equals the sum of the leaves below it.
+    #[derive(Debug, Clone)]
+    struct AdditiveHasher;
+
+    impl MerkleHasher for AdditiveHasher {
+        fn hash(a: &Felt, b: &Felt) -> Felt {
+            a + b
+        }
+    }
+
+    std::thread_local! {
+        static HASH_CALLS: core::cell::Cell<usize> = const { core::cell::Cell::new(0) };
+    }
+
+    /// Poseidon hasher counting its calls on the current thread.
+    #[derive(Debug)]
+    struct CountingHasher;
+
+    impl MerkleHasher for CountingHasher {
+        fn hash(a: &Felt, b: &Felt) -> Felt {
+            HASH_CALLS.with(|calls| calls.set(calls.get() + 1));
+            PoseidonHasher::hash(a, b)
+        }
+    }
+
+    #[test]
+    fn test_add_leaf_hashes_once_per_level() {
+        let mut tree = HybridMerkleTree::<CountingHasher>::with_hasher(8);
+        for i in 0..20u32 {
+            HASH_CALLS.with(|calls| calls.set(0));
+            tree.add_leaf(&Felt::from(i)).unwrap();
+            assert_eq!(HASH_CALLS.with(|calls| calls.get()), 7);
+        }
+    }
+
+    #[test]
+    fn test_tree_with_custom_hasher() {
+        let mut tree = HybridMerkleTree::<AdditiveHasher>::with_hasher_and_null_base(3, Felt::ZERO);
+        for i in 1..=3 {
+            tree.add_leaf(&Felt::from(i as u32)).unwrap();
+        }
+        assert_eq!(tree.root(), Felt::from(6));
+
+        let (proof, _bits) = tree.path(LeafIndex(2)).unwrap();
+        assert_eq!(proof, vec![Felt::ZERO, Felt::from(3)]);
+        assert_eq!(
+            compute_merkle_root_rust::<AdditiveHasher>(Felt::from(3), 2, &proof),
+            tree.root()
+        );
+        assert_eq!(
+            precomputed_hashes::<AdditiveHasher>(3, Felt::ONE),
+            vec![Felt::ONE, Felt::TWO, Felt::from(4)]
+        );
+    }
+
+    #[test]
+    fn test_null_hashes_table_matches_runtime_hashes() {
+        let computed =
+            hash_null_levels::<PoseidonHasher>(NULL_HASHES.len() + 1, default_null_base());
+        let table: Vec<Felt> = NULL_HASHES.iter().map(Felt::from_bytes_be).collect();
+        assert_eq!(computed[..NULL_HASHES.len()], table[..]);
+        for height in 1..=NULL_HASHES.len() {
+            assert_eq!(
+                precomputed_hashes::<PoseidonHasher>(height, default_null_base()),
+                &computed[..height]
+            );
+        }
+        assert_eq!(table[0], default_null_base());
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_default_trees_share_cached_null_hashes() {
+        use crate::hasher::CACHED_NULL_HASHES_HEIGHT;
+
+        let first = HybridMerkleTree::new(32);
+        let second = HybridMerkleTree::new(32);
+        assert_eq!(first.precomputed, second.precomputed);
+        assert_eq!(
+            first.precomputed,
+            hash_null_levels::<PoseidonHasher>(32, default_null_base())
+        );
+
+        let cached = PoseidonHasher::cached_null_hashes().unwrap();
+        assert_eq!(cached.len(), CACHED_NULL_HASHES_HEIGHT);
+        assert!(core::ptr::eq(
+            cached,
+            PoseidonHasher::cached_null_hashes().unwrap()
+        ));
+        // Other bases and hashers bypass the cache.
+        assert_eq!(
+            HybridMerkleTree::with_null_base(32, Felt::ZERO).precomputed,
+            hash_null_levels::<PoseidonHasher>(32, Felt::ZERO)
+        );
+        assert_eq!(crate::hasher::PedersenHasher::cached_null_hashes(), None);
+    }
+
+    #[test]
+    fn test_pedersen_null_hashes_with_zero_base() {
+        use crate::hasher::PedersenHasher;
+        use starknet_types_core::hash::{Pedersen, StarkHash};
+
+        let nulls = precomputed_hashes::<PedersenHasher>(4, Felt::ZERO);
+        let level1 = Pedersen::hash(&Felt::ZERO, &Felt::ZERO);
+        assert_eq!(
+            level1,
+            Felt::from_hex("0x49ee3eba8c1600700ee1b87eb599f16716b0b1022947733551fde4050ca6804")
+                .unwrap()
+        );
+        let level2 = Pedersen::hash(&level1, &level1);
+        let level3 = Pedersen::hash(&level2, &level2);
+        assert_eq!(nulls, vec![Felt::ZERO, level1, level2, level3]);
+        assert_ne!(nulls, precomputed_hashes::<PoseidonHasher>(4, Felt::ZERO));
+
+        let tree = HybridMerkleTree::<PedersenHasher>::with_hasher_and_null_base(4, Felt::ZERO);
+        assert_eq!(tree.root(), level3);
+    }
+
+    #[test]
+    fn test_tree_with_pedersen_hasher() {
+        use crate::hasher::PedersenHasher;
+        use starknet_types_core::hash::{Pedersen, StarkHash};
+
+        let leaves: Vec<Felt> = (1..=3).map(|i| Felt::from(i as u32)).collect();
+        let mut tree = HybridMerkleTree::<PedersenHasher>::with_hasher_and_null_base(3, Felt::ZERO);
+        for leaf in &leaves {
+            tree.add_leaf(leaf).unwrap();
+        }
+        let expected = Pedersen::hash(
+            &Pedersen::hash(&leaves[0], &leaves[1]),
+            &Pedersen::hash(&leaves[2], &Felt::ZERO),
+        );
+        assert_eq!(tree.root(), expected);
+        assert_eq!(
+            tree.root(),
+            Felt::from_hex_unchecked(
+                "0x4830fe2e47fca6dd87be35c6c4c0be2519aa0ef2af2e5751fc1934dfa536705"
+            )
+        );
+    }
+
+    #[test]
+    fn test_verify_pedersen_proofs_with_generic_verifiers() {
+        use crate::hasher::PedersenHasher;
+
+        let leaves: Vec<Felt> = (1..=5).map(|i| Felt::from(i as u32)).collect();
+        let tree = HybridMerkleTree::<PedersenHasher>::from_leaves_with_hasher(4, &leaves).unwrap();
+        let root = tree.root();
+        let (path, _) = tree.path(LeafIndex(2)).unwrap();
+        assert_eq!(
+            verify_proof::<PedersenHasher>(&root, &leaves[2], 2, &path, 4),
+            Ok(true)
+        );
+        // The Poseidon verifier rejects the same proof instead of reporting a wrong root.
+        assert_eq!(
+            verify_proof::<PoseidonHasher>(&root, &leaves[2], 2, &path, 4),
+            Ok(false)
+        );
+        assert_eq!(
+            compute_and_verify::<PedersenHasher>(&root, &leaves[2], 2, &path),
+            (true, root)
+        );
+
+        let proof = tree.proof(LeafIndex(2)).unwrap();
+        assert!(proof.verify_with_hasher::<PedersenHasher>(&root));
+        assert!(!proof.verify(&root));
+
+        let items = vec![
+            (leaves[0], 0, tree.path(LeafIndex(0)).unwrap().0),
+            (leaves[2], 2, path),
+        ];
+        assert_eq!(
+            verify_batch::<PedersenHasher>(&root, &items, 4),
+            vec![true, true]
+        );
+        let indices = [1, 4];
+        let multi = tree.multi_path(&indices).unwrap();
+        assert!(verify_multi_proof::<PedersenHasher>(
+            &root,
+            &[leaves[1], leaves[4]],
+            &indices,
+            &multi
+        ));
+        let range = tree.range_proof(1, 4).unwrap();
+        assert!(verify_range_proof::<PedersenHasher>(
+            &root,
+            &leaves[1..4],
+            1,
+            &range
+        ));
+    }
+
+    #[test]
+    fn test_diff_root_finds_first_mismatching_node() {
+        let leaves: Vec<Felt> = (1..=5).map(|i| Felt::from(i as u32)).collect();
+        let tree = HybridMerkleTree::from_leaves(4, &leaves).unwrap();
+        assert_eq!(tree.diff_root(&tree.clone()), None);
+
+        let mut changed = tree.clone();
+        changed.update_leaf(LeafIndex(2), &Felt::from(30)).unwrap();
+        assert_eq!(tree.diff_root(&changed), Some((0, 2)));
+        // Above the leaves, the mismatch propagates to the ancestors of leaf 2 only.
+        for (level, index) in [(1, 1), (2, 0), (3, 0)] {
+            assert_ne!(
+                tree.store.get(level, index),
+                changed.store.get(level, index)
+            );
+        }
+        assert_eq!(tree.store.get(1, 0), changed.store.get(1, 0));
+        assert_eq!(tree.store.get(2, 1), changed.store.get(2, 1));
+
+        // Same leaves over another null base: leaf 4 is padded with a different null.
+        let mut rebased = HybridMerkleTree::with_null_base(4, Felt::ZERO);
+        rebased.add_leaves(&leaves).unwrap();
+        assert_eq!(tree.diff_root(&rebased), Some((1, 2)));
+
+        let mut longer = tree.clone();
+        longer.add_leaf(&Felt::from(6)).unwrap();
+        assert_eq!(tree.diff_root(&longer), Some((0, 5)));
+        assert_eq!(
+            HybridMerkleTree::new(4).diff_root(&HybridMerkleTree::with_null_base(4, Felt::ZERO)),
+            Some((3, 0))
+        );
+    }
+
+    #[test]
+    fn test_subtree_roots_follow_leaf_count_bits() {
+        let leaves: Vec<Felt> = (1..=8u32).map(Felt::from).collect();
+        let mut tree = HybridMerkleTree::new(4);
+        assert!(tree.subtree_roots().is_empty());
+        tree.add_leaves(&leaves[..5]).unwrap();
+        let peaks = tree.subtree_roots();
+        assert_eq!(
+            peaks,
+            vec![
+                HybridMerkleTree::from_leaves(3, &leaves[..4])
+                    .unwrap()
+                    .root(),
+                leaves[4]
+            ]
+        );
+        assert_eq!(peaks[0], tree.store.get(2, 0).unwrap());
+
+        tree.add_leaf(&leaves[5]).unwrap();
+        tree.add_leaf(&leaves[6]).unwrap();
+        assert_eq!(tree.subtree_roots().len(), 3);
+        assert_eq!(tree.subtree_roots()[1], tree.store.get(1, 2).unwrap());
+        tree.add_leaf(&leaves[7]).unwrap();
+        assert_eq!(tree.subtree_roots(), vec![tree.root()]);
+
+        let tall = HybridMerkleTree::from_leaves(70, &leaves[..3]).unwrap();
+        let short = HybridMerkleTree::from_leaves(4, &leaves[..3]).unwrap();
+        assert_eq!(tall.subtree_roots(), short.subtree_roots());
+    }
+
+    #[test]
+    fn test_noir_witness_recomputes_root() {
+        for padding in [Padding::Right, Padding::Left] {
+            let mut tree = HybridMerkleTree::builder()
+                .height(5)
+                .padding(padding)
+                .build();
+            tree.add_leaves(&(1..=11u32).map(Felt::from).collect::<Vec<_>>())
+                .unwrap();
+            for index in [0, 6, 10] {
+                let (leaf, position, hash_path) = tree.to_noir_witness(LeafIndex(index)).unwrap();
+                assert_eq!(leaf, Felt::from(index as u32 + 1));
+                assert_eq!(position, Felt::from(tree.position(LeafIndex(index))));
+                assert_eq!(hash_path.len(), 4);
+                let position = u64::try_from(position).unwrap() as usize;
+                assert_eq!(
+                    compute_merkle_root_rust::<PoseidonHasher>(leaf, position, &hash_path),
+                    tree.root()
+                );
+            }
+            assert_eq!(
+                tree.to_noir_witness(LeafIndex(11)),
+                Err(MerkleError::LeafNotFound { index: 11, len: 11 })
+            );
+        }
+    }
+
+    #[test]
+    fn test_add_leaves_returns_contiguous_indices() {
+        let mut tree = HybridMerkleTree::new(4);
+        tree.add_leaf(&Felt::from(1)).unwrap();
+        let leaves: Vec<Felt> = (2..=5).map(|i| Felt::from(i as u32)).collect();
+        let indices = tree.add_leaves(&leaves).unwrap();
+        assert_eq!(indices, 1..5);
+        for (index, leaf) in indices.zip(&leaves) {
+            assert_eq!(tree.get_leaf(LeafIndex(index)), Some(*leaf));
+        }
+
+        let mut incremental = HybridMerkleTree::new(4);
+        for i in 1..=5 {
+            incremental.add_leaf(&Felt::from(i as u32)).unwrap();
+        }
+        assert_eq!(tree.root(), incremental.root());
+
+        let too_many = vec![Felt::ZERO; 4];
+        assert_eq!(
+            tree.add_leaves(&too_many),
+            Err(MerkleError::TreeFull { capacity: 8 })
+        );
+        assert_eq!(
+            tree.len(),
+            5,
+            "A rejected batch must not be partially inserted"
+        );
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn test_parallel_from_leaves_matches_sequential() {
+        let leaves: Vec<Felt> = (0..(PARALLEL_MIN_NODES as u32 + 1))
+            .map(Felt::from)
+            .collect();
+        let null = default_null_base();
+        assert_eq!(
+            parent_layer_par::<PoseidonHasher>(&leaves, &null),
+            parent_layer::<PoseidonHasher>(&leaves, &null)
+        );
+
+        let parallel = HybridMerkleTree::from_leaves(12, &leaves).unwrap();
+        let mut sequential = HybridMerkleTree::new(12);
+        sequential.add_leaves(&leaves).unwrap();
+        assert_eq!(parallel.root(), sequential.root());
+        assert_eq!(parallel.store.layers, sequential.store.layers);
+    }
+
+    #[test]
+    fn test_root_of_matches_from_leaves() {
+        let leaves: Vec<Felt> = (1..=5).map(|i| Felt::from(i as u32)).collect();
+        for height in [4, 8] {
+            assert_eq!(
+                HybridMerkleTree::root_of(height, &leaves).unwrap(),
+                HybridMerkleTree::from_leaves(height, &leaves)
+                    .unwrap()
+                    .root()
+            );
+        }
+        assert_eq!(
+            HybridMerkleTree::root_of(4, &[]).unwrap(),
+            HybridMerkleTree::new(4).root()
+        );
+        assert_eq!(
+            HybridMerkleTree::root_of(2, &leaves),
+            Err(MerkleError::TreeFull { capacity: 2 })
+        );
+    }
+
+    #[test]
+    fn test_multi_proof() {
+        let mut tree = HybridMerkleTree::new(4);
+        let leaves: Vec<Felt> = (1..=6).map(|i| Felt::from(i as u32)).collect();
+        tree.add_leaves(&leaves).unwrap();
+        let root = tree.root();
+        let indices = [0, 1, 2, 3];
+
+        let proof = tree.multi_path(&indices).unwrap();
+        assert!(verify_multi_proof::<PoseidonHasher>(
+            &root,
+            &leaves[..4],
+            &indices,
+            &proof
+        ));
+        let separate: usize = indices
+            .iter()
+            .map(|&index| tree.path(LeafIndex(index)).unwrap().0.len())
+            .sum();
+        assert_eq!(proof.siblings.len(), 1);
+        assert!(proof.siblings.len() < separate);
+
+        let sparse = tree.multi_path(&[5, 0]).unwrap();
+        assert!(verify_multi_proof::<PoseidonHasher>(
+            &root,
+            &[leaves[5], leaves[0]],
+            &[5, 0],
+            &sparse
+        ));
+        assert!(!verify_multi_proof::<PoseidonHasher>(
+            &root,
+            &[leaves[0], leaves[5]],
+            &[5, 0],
+            &sparse
+        ));
+        assert!(!verify_multi_proof::<PoseidonHasher>(
+            &root,
+            &leaves[..4],
+            &indices,
+            &sparse
+        ));
+    }
+
+    #[test]
+    fn test_left_child_proof_before_and_after_right_sibling() {
+        let mut tree = HybridMerkleTree::new(4);
+        let leaf0 = Felt::from(1);
+        tree.add_leaf(&leaf0).unwrap();
+        let before = tree.proof(LeafIndex(0)).unwrap();
+        assert!(before.verify(&tree.root()));
+
+        tree.add_leaf(&Felt::from(2)).unwrap();
+        let after = tree.proof(LeafIndex(0)).unwrap();
+        assert!(after.verify(&tree.root()));
+        assert!(!before.verify(&tree.root()));
+        assert_eq!(after.siblings[0], Felt::from(2));
+        assert_eq!(
+            tree.store.layers,
+            HybridMerkleTree::from_leaves(4, &[leaf0, Felt::from(2)])
+                .unwrap()
+                .store
+                .layers
+        );
+    }
+
+    #[test]
+    fn test_tree_equality_ignores_insertion_strategy() {
+        let leaves: Vec<Felt> = (1..=5).map(|i| Felt::from(i as u32)).collect();
+        let mut incremental = HybridMerkleTree::new(4).with_index_map();
+        incremental.add_leaves(&leaves).unwrap();
+        let bulk = HybridMerkleTree::from_leaves(4, &leaves).unwrap();
+        assert_eq!(incremental, bulk);
+
+        assert_ne!(HybridMerkleTree::new(4), HybridMerkleTree::new(5));
+        assert_ne!(
+            HybridMerkleTree::new(4),
+            HybridMerkleTree::with_null_base(4, Felt::from(1))
+        );
+        let mut other = bulk.clone();
+        other.update_leaf(LeafIndex(2), &Felt::from(9)).unwrap();
+        assert_ne!(other, bulk);
+    }
+
+    #[test]
+    fn test_index_bits_round_trip() {
+        let mut tree = HybridMerkleTree::new(4);
+        tree.add_leaves(&[Felt::from(1); 6]).unwrap();
+        for index in 0..6 {
+            let (_siblings, bits) = tree.path(LeafIndex(index)).unwrap();
+            assert_eq!(bits, bits_from_index(index, 4));
+            assert_eq!(index_from_bits(&bits), index);
+        }
+
+        // Pseudo-random indices below the capacity of a height-32 tree.
+        let height = 32;
+        let mut index = 12345usize;
+        for _ in 0..1000 {
+            index = (index
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407))
+                % (1 << (height - 1));
+            assert_eq!(index_from_bits(&bits_from_index(index, height)), index);
+        }
+    }
+
+    #[test]
+    fn test_hash_leaf_data() {
+        use starknet_types_core::hash::{Poseidon, StarkHash};
+
+        let record = b"deposit:0xabc:1000000000000000000:nonce-000000000042";
+        assert_eq!(record.len(), 52);
+        assert_eq!(hash_leaf_data(record), hash_leaf_data(record));
+
+        let mut high = [0u8; 32];
+        high[1..].copy_from_slice(&record[..31]);
+        let mut low = [0u8; 32];
+        low[11..].copy_from_slice(&record[31..]);
+        assert_eq!(
+            hash_leaf_data(record),
+            Poseidon::hash_array(&[
+                Felt::from(52),
+                Felt::from_bytes_be(&high),
+                Felt::from_bytes_be(&low)
+            ])
+        );
+        assert_ne!(hash_leaf_data(b"ab"), hash_leaf_data(b"ab\0"));
+
+        let mut tree = HybridMerkleTree::new(3);
+        let index = tree.add_leaf_data(record).unwrap();
+        assert_eq!(
+            tree.get_leaf(LeafIndex(index)),
+            Some(hash_leaf_data(record))
+        );
+    }
+
+    #[test]
+    fn test_add_leaf_str_parses_hex_and_decimal() {
+        let mut tree = HybridMerkleTree::new(3);
+        assert_eq!(tree.add_leaf_str("0x2a"), Ok(0));
+        assert_eq!(tree.add_leaf_str("42"), Ok(1));
+        assert_eq!(tree.get_leaf(LeafIndex(0)), Some(Felt::from(42)));
+        assert_eq!(tree.get_leaf(LeafIndex(0)), tree.get_leaf(LeafIndex(1)));
+        assert_eq!(parse_felt("0x0"), Ok(Felt::ZERO));
+        assert_eq!(parse_felt("0x002A"), Ok(Felt::from(42)));
+
+        // The field modulus would silently reduce to zero.
+        let modulus = "0x800000000000011000000000000000000000000000000000000000000000001";
+        for bad in ["", "0x", "-1", "4x2", modulus] {
+            assert_eq!(
+                tree.add_leaf_str(bad),
+                Err(MerkleError::InvalidFelt {
+                    value: bad.to_string()
+                })
+            );
+        }
+        assert_eq!(tree.len(), 2);
+    }
+
+    #[test]
+    fn test_proof_bytes_round_trip() {
+        let mut tree = HybridMerkleTree::new(11);
+        tree.add_leaves(&(1..=7).map(Felt::from).collect::<Vec<_>>())
+            .unwrap();
+        let proof = tree.proof(LeafIndex(6)).unwrap();
+        let bytes = proof.to_bytes();
+        assert_eq!(bytes.len(), 8 + 32 + 4 + 32 * 10 + 2);
+        assert_eq!(&bytes[..8], &6u64.to_le_bytes());
+        assert_eq!(MerkleProof::from_bytes(&bytes), Ok(proof.clone()));
+        assert!(MerkleProof::from_bytes(&bytes)
+            .unwrap()
+            .verify(&tree.root()));
+
+        assert_eq!(
+            MerkleProof::from_bytes(&bytes[..bytes.len() - 1]),
+            Err(MerkleError::InvalidProofEncoding {
+                expected: bytes.len(),
+                actual: bytes.len() - 1
+            })
+        );
+        assert_eq!(
+            MerkleProof::from_bytes(&bytes[..10]),
+            Err(MerkleError::InvalidProofEncoding {
+                expected: 44,
+                actual: 10
+            })
+        );
+    }
+
+    #[test]
+    fn test_felt_bytes_endianness() {
+        let felt = Felt::from_hex_unchecked("0x102030405060708090a0b0c0d0e0f");
+        let be = felt_to_bytes(&felt, Endianness::Big);
+        let le = felt_to_bytes(&felt, Endianness::Little);
+        assert_eq!(be[31], 0x0f);
+        assert_eq!(le[0], 0x0f);
+        let mut reversed = be;
+        reversed.reverse();
+        assert_eq!(le, reversed);
+        for endianness in [Endianness::Big, Endianness::Little] {
+            assert_eq!(
+                felt_from_bytes(&felt_to_bytes(&felt, endianness), endianness),
+                felt
+            );
+        }
+
+        let mut tree = HybridMerkleTree::new(4);
+        tree.add_leaves(&[1u32, 2, 3].map(Felt::from)).unwrap();
+        assert_eq!(
+            tree.root_bytes_with(Endianness::Big),
+            tree.root().to_bytes_be()
+        );
+        assert_eq!(
+            tree.root_bytes_with(Endianness::Little),
+            tree.root().to_bytes_le()
+        );
+
+        let proof = tree.proof(LeafIndex(2)).unwrap();
+        let le_bytes = proof.to_bytes_with(Endianness::Little);
+        assert_eq!(&le_bytes[8..40], &proof.leaf.to_bytes_le());
+        assert_eq!(
+            MerkleProof::from_bytes_with(&le_bytes, Endianness::Little),
+            Ok(proof.clone())
+        );
+        assert_eq!(proof.to_bytes_with(Endianness::Big), proof.to_bytes());
+        assert_ne!(MerkleProof::from_bytes(&le_bytes), Ok(proof));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_proof_json_round_trip() {
+        let mut tree = HybridMerkleTree::new(4);
+        tree.add_leaves(&[1u32, 2, 3, 4, 5].map(Felt::from))
+            .unwrap();
+        let proof = tree.proof(LeafIndex(4)).unwrap();
+        let json = proof.to_json(&tree.root());
+        assert_eq!(
+            MerkleProof::from_json(&json),
+            Ok((proof.clone(), tree.root()))
+        );
+
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(value["index"], 4);
+        assert_eq!(value["siblings"].as_array().unwrap().len(), 3);
+        assert_eq!(value["leaf"], format!("0x{:0>64}", "5"));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_proof_json_fixture() {
+        // The height-2 Starknet Poseidon vector of tests/vectors.rs.
+        let fixture = r#"{
+            "leaf": "0x03d937c035c878245caf64531a5756109c53068da139362728feb561405371cb",
+            "index": 0,
+            "siblings": ["0x0208a0a10250e382e1e4bbe2880906c2791bf6275695e02fbbc6aeff9cd8b31a"],
+            "root": "0x67c6a2e2d0c7867f97444ae17956dbc89d40ad22255bb06f5f6c515958926ed"
+        }"#;
+        let (proof, root) = MerkleProof::from_json(fixture).unwrap();
+        assert_eq!(proof.is_right, vec![false]);
+        assert!(proof.verify(&root));
+
+        assert!(matches!(
+            MerkleProof::from_json(r#"{"leaf": "0x1", "index": 0, "siblings": []}"#),
+            Err(MerkleError::InvalidProofJson { .. })
+        ));
+        assert_eq!(
+            MerkleProof::from_json(
+                r#"{"leaf": "0x1", "index": 2, "siblings": ["0x2"], "root": "0x3"}"#
+            )
+            .map(|_| ()),
+            Err(MerkleError::IndexOutOfRange {
+                index: 2,
+                capacity: 2
+            })
+        );
+    }
+
+    #[test]
+    fn test_oz_format_round_trip() {
+        let mut tree = HybridMerkleTree::new(5);
+        tree.add_leaves(&(1..=11).map(Felt::from).collect::<Vec<_>>())
+            .unwrap();
+        let proof = tree.proof(LeafIndex(10)).unwrap();
+        let (siblings, directions) = proof.to_oz_format().unwrap();
+        assert_eq!(directions, 0b1010);
+        assert_eq!(siblings[0], proof.siblings[0].to_bytes_be());
+
+        let decoded = MerkleProof::from_oz_format(proof.leaf, &siblings, directions).unwrap();
+        assert_eq!(decoded, proof);
+        assert!(decoded.verify(&tree.root()));
+        assert_eq!(
+            MerkleProof::from_oz_format(proof.leaf, &siblings, 1 << 4),
+            Err(MerkleError::IndexOutOfRange {
+                index: 16,
+                capacity: 16
+            })
+        );
+
+        let mut tall = HybridMerkleTree::new(70);
+        tall.add_leaf(&Felt::ONE).unwrap();
+        assert_eq!(
+            tall.proof(LeafIndex(0)).unwrap().to_oz_format(),
+            Err(MerkleError::ProofLengthMismatch {
+                expected: 64,
+                actual: 69
+            })
+        );
+    }
+
+    #[test]
+    fn test_rehash_repairs_upper_layers() {
+        let leaves: Vec<Felt> = (1..=5).map(Felt::from).collect();
+        let expected = HybridMerkleTree::from_leaves(4, &leaves).unwrap();
+        let mut tree = expected.clone();
+        tree.store.layers[1][0] = Felt::from(99);
+        tree.store.layers[3][0] = Felt::from(99);
+        tree.restore_left_path();
+        assert_ne!(tree.root(), expected.root());
+
+        tree.rehash();
+        assert_eq!(tree.root(), expected.root());
+        assert_eq!(tree.store, expected.store);
+        assert_eq!(tree.path(LeafIndex(4)), expected.path(LeafIndex(4)));
+    }
+
+    #[cfg(feature = "metrics")]
+    #[test]
+    fn test_hash_count() {
+        let mut tree = HybridMerkleTree::new(5);
+        assert_eq!(tree.hash_count(), 0);
+        tree.add_leaves(&[Felt::from(1), Felt::from(2), Felt::from(3)])
+            .unwrap();
+        assert_eq!(tree.hash_count(), 3 * 4);
+        tree.update_leaf(LeafIndex(0), &Felt::from(4)).unwrap();
+        assert_eq!(tree.hash_count(), 4 * 4);
+
+        // Five leaves hash into 3, 2, 1 and 1 nodes on the levels above.
+        let leaves: Vec<Felt> = (1..=5).map(Felt::from).collect();
+        let mut bulk = HybridMerkleTree::from_leaves(5, &leaves).unwrap();
+        assert_eq!(bulk.hash_count(), 3 + 2 + 1 + 1);
+        bulk.rehash();
+        assert_eq!(bulk.hash_count(), 2 * 7);
+    }
+
+    #[test]
+    fn test_add_leaf_with_proof_matches_path() {
+        for padding in [Padding::Right, Padding::Left] {
+            let mut tree = HybridMerkleTree::builder()
+                .height(4)
+                .padding(padding)
+                .build();
+            for i in 1..=8u32 {
+                let (index, proof) = tree.add_leaf_with_proof(&Felt::from(i)).unwrap();
+                assert!(proof.verify(&tree.root()));
+                assert_eq!(proof, tree.proof(LeafIndex(index)).unwrap());
+            }
+            assert_eq!(
+                tree.add_leaf_with_proof(&Felt::from(9)),
+                Err(MerkleError::TreeFull { capacity: 8 })
+            );
+        }
+    }
+
+    #[test]
+    fn test_layer_stats_follow_leaf_count() {
+        let mut tree = HybridMerkleTree::new(32);
+        for i in 0..100u32 {
+            tree.add_leaf(&Felt::from(i)).unwrap();
+        }
+        let stats = tree.layer_stats();
+        assert_eq!(stats.len(), 32);
+        assert_eq!(
+            &stats[..8],
+            &[
+                (0, 100),
+                (1, 50),
+                (2, 25),
+                (3, 13),
+                (4, 7),
+                (5, 4),
+                (6, 2),
+                (7, 1)
+            ]
+        );
+        assert!(stats[8..].iter().all(|&(_, count)| count == 1));
+        assert_eq!(tree.node_count(), 202 + 24);
+    }
+
+    #[test]
+    fn test_merge_matches_single_tree() {
+        let leaves: Vec<Felt> = (1..=6u32).map(Felt::from).collect();
+        let mut left = HybridMerkleTree::from_leaves(4, &leaves[..2]).unwrap();
+        let right = HybridMerkleTree::from_leaves(4, &leaves[2..]).unwrap();
+        left.merge(&right).unwrap();
+        assert_eq!(left, HybridMerkleTree::from_leaves(4, &leaves).unwrap());
+        assert!(left.proof(LeafIndex(5)).unwrap().verify(&left.root()));
+
+        let domain = Felt::from(7);
+        let build = |leaves: &[Felt]| {
+            let mut tree = HybridMerkleTree::builder()
+                .height(4)
+                .leaf_domain(domain)
+                .build();
+            tree.add_leaves(leaves).unwrap();
+            tree
+        };
+        let mut tagged = build(&leaves[..3]);
+        tagged.merge(&build(&leaves[3..])).unwrap();
+        assert_eq!(tagged.root(), build(&leaves).root());
+
+        assert_eq!(
+            left.merge(&HybridMerkleTree::new(3)),
+            Err(MerkleError::HeightMismatch {
+                expected: 4,
+                actual: 3
+            })
+        );
+        assert_eq!(
+            left.merge(&right),
+            Err(MerkleError::TreeFull { capacity: 8 })
+        );
+        assert_eq!(left.len(), 6);
+    }
+
+    #[test]
+    fn test_with_capacity_reserves_layers() {
+        let leaves: Vec<Felt> = (0..100u32).map(Felt::from).collect();
+        let mut tree = HybridMerkleTree::with_capacity(8, 100);
+        let reserved: Vec<usize> = tree.store.layers.iter().map(Vec::capacity).collect();
+        assert!(reserved[0] >= 100 && reserved[1] >= 50 && reserved[7] >= 1);
+        tree.add_leaves(&leaves).unwrap();
+        assert_eq!(tree, HybridMerkleTree::from_leaves(8, &leaves).unwrap());
+        for (layer, reserved) in tree.store.layers.iter().zip(reserved) {
+            assert_eq!(layer.capacity(), reserved, "Appending did not reallocate");
+        }
+        // Reservations never exceed what the tree can hold.
+        assert!(HybridMerkleTree::with_capacity(3, usize::MAX).store.layers[0].capacity() < 8);
+        let tall = HybridMerkleTree::with_capacity(70, 4);
+        assert!(tall.store.layers[69].capacity() >= 1);
+    }
+
+    #[test]
+    fn test_add_leaf_return_root() {
+        let mut tree = HybridMerkleTree::new(3);
+        for i in 1..=4u32 {
+            let root = tree.add_leaf_return_root(&Felt::from(i)).unwrap();
+            assert_eq!(root, tree.root());
+        }
+        assert_eq!(
+            tree.add_leaf_return_root(&Felt::from(5)),
+            Err(MerkleError::TreeFull { capacity: 4 })
+        );
+    }
+
+    #[test]
+    fn test_refresh_proof_after_appends() {
+        for padding in [Padding::Right, Padding::Left] {
+            let mut tree = HybridMerkleTree::builder()
+                .height(4)
+                .padding(padding)
+                .build();
+            tree.add_leaf(&Felt::from(1)).unwrap();
+            let mut proof = tree.proof(LeafIndex(0)).unwrap();
+            for i in 2..=8u32 {
+                tree.add_leaf(&Felt::from(i)).unwrap();
+            }
+            assert!(!proof.verify(&tree.root()));
+            tree.refresh_proof(&mut proof).unwrap();
+            assert!(proof.verify(&tree.root()));
+            assert_eq!(proof, tree.proof(LeafIndex(0)).unwrap());
+        }
+
+        let tree = HybridMerkleTree::new(4);
+        let mut short = MerkleProof {
+            leaf: Felt::ZERO,
+            index: LeafIndex(0),
+            siblings: vec![Felt::ZERO],
+            is_right: vec![false],
+        };
+        assert_eq!(
+            tree.refresh_proof(&mut short),
+            Err(MerkleError::ProofLengthMismatch {
+                expected: 3,
+                actual: 1
+            })
+        );
+
+        let mut tree = HybridMerkleTree::builder()
+            .height(4)
+            .padding(Padding::Left)
+            .build();
+        tree.add_leaf(&Felt::from(1)).unwrap();
+        let mut out_of_range = tree.proof(LeafIndex(0)).unwrap();
+        out_of_range.index = LeafIndex(8);
+        assert_eq!(
+            tree.refresh_proof(&mut out_of_range),
+            Err(MerkleError::IndexOutOfRange {
+                index: 8,
+                capacity: 8
+            })
+        );
+    }
+
+    #[test]
+    fn test_height_1_tree_holds_one_leaf() {
+        let leaf = Felt::from(42);
+        let mut tree = HybridMerkleTree::new(1);
+        assert_eq!(tree.capacity(), 1);
+        assert_eq!(tree.root(), default_null_base());
+        assert_eq!(tree.add_leaf(&leaf), Ok(0));
+        assert_eq!(tree.root(), leaf, "The single leaf is the root");
+        assert_eq!(tree.path(LeafIndex(0)), Ok((vec![], vec![])));
+        assert!(tree.proof(LeafIndex(0)).unwrap().verify(&tree.root()));
+        assert_eq!(
+            tree.add_leaf(&Felt::from(1)),
+            Err(MerkleError::TreeFull { capacity: 1 })
+        );
+
+        tree.update_leaf(LeafIndex(0), &Felt::from(7)).unwrap();
+        assert_eq!(tree.root(), Felt::from(7));
+        assert_eq!(
+            HybridMerkleTree::from_leaves(1, &[leaf]).unwrap().root(),
+            leaf
+        );
+        assert_eq!(HybridMerkleTree::root_of(1, &[leaf]), Ok(leaf));
+    }
+
+    #[test]
+    fn test_height_0_is_rejected() {
+        assert_eq!(
+            HybridMerkleTree::from_leaves(0, &[]).unwrap_err(),
+            MerkleError::InvalidHeight { height: 0 }
+        );
+        assert_eq!(
+            HybridMerkleTree::root_of(0, &[]),
+            Err(MerkleError::InvalidHeight { height: 0 })
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "tree height must be at least 1, got 0")]
+    fn test_new_panics_on_height_0() {
+        HybridMerkleTree::new(0);
+    }
+
+    #[test]
+    fn test_compute_root_from_bits_matches_index() {
+        let mut tree = HybridMerkleTree::new(4);
+        let leaves: Vec<Felt> = (1..=6).map(Felt::from).collect();
+        tree.add_leaves(&leaves).unwrap();
+        for (index, leaf) in leaves.iter().enumerate() {
+            let (siblings, bits) = tree.path(LeafIndex(index)).unwrap();
+            let root = compute_root_from_bits::<PoseidonHasher>(leaf, &bits, &siblings);
+            assert_eq!(
+                root,
+                compute_merkle_root_rust::<PoseidonHasher>(*leaf, index, &siblings)
+            );
+            assert_eq!(root, tree.root());
+        }
+    }
+
+    #[test]
+    fn test_truncate_matches_fresh_tree() {
+        let leaves: Vec<Felt> = (1..=8).map(Felt::from).collect();
+        let mut tree = HybridMerkleTree::new(5).with_index_map();
+        tree.add_leaves(&leaves).unwrap();
+        tree.truncate(3).unwrap();
+
+        let fresh = HybridMerkleTree::from_leaves(5, &leaves[..3]).unwrap();
+        assert_eq!(tree, fresh);
+        assert_eq!(tree.store, fresh.store);
+        assert_eq!(tree.path(LeafIndex(2)), fresh.path(LeafIndex(2)));
+        assert_eq!(tree.index_of(&leaves[5]), None);
+
+        tree.add_leaf(&leaves[3]).unwrap();
+        assert_eq!(
+            tree.root(),
+            HybridMerkleTree::root_of(5, &leaves[..4]).unwrap()
+        );
+        tree.truncate(0).unwrap();
+        assert_eq!(tree.root(), tree.empty_root());
+        assert_eq!(
+            tree.truncate(1),
+            Err(MerkleError::TruncateOutOfRange { new_len: 1, len: 0 })
+        );
+    }
+
+    #[test]
+    fn test_extend_from_iterator() {
+        let mut tree = HybridMerkleTree::new(4);
+        assert_eq!(tree.extend((1..=5u32).map(Felt::from)), Ok(0..5));
+        assert_eq!(tree.len(), 5);
+        let leaves: Vec<Felt> = (1..=5u32).map(Felt::from).collect();
+        assert_eq!(tree.root(), HybridMerkleTree::root_of(4, &leaves).unwrap());
+
+        assert_eq!(
+            tree.extend((6..=10u32).map(Felt::from)),
+            Err(MerkleError::TreeFull { capacity: 8 })
+        );
+        assert_eq!(tree.len(), 8, "Leaves before the overflow are kept");
+    }
+
+    #[test]
+    fn test_default_tree() {
+        let tree = HybridMerkleTree::default();
+        assert_eq!(tree.height(), 32);
+        assert_eq!(tree.height(), DEFAULT_HEIGHT);
+        assert!(tree.is_empty());
+        assert_eq!(
+            tree.root(),
+            HybridMerkleTree::empty_root_for(DEFAULT_HEIGHT)
+        );
+    }
+
+    #[test]
+    fn test_collect_picks_minimal_height() {
+        let leaves: Vec<Felt> = (1..=5u32).map(Felt::from).collect();
+        let tree: HybridMerkleTree = leaves.iter().cloned().collect();
+        assert_eq!(tree.height(), 4);
+        assert_eq!(tree.capacity(), 8);
+        assert_eq!(tree, HybridMerkleTree::from_leaves(4, &leaves).unwrap());
+        assert_eq!(HybridMerkleTree::from(&leaves[..4]).height(), 3);
+
+        for (len, height) in [(0, 1), (1, 1), (2, 2), (3, 3), (8, 4), (9, 5)] {
+            assert_eq!(height_for(len), height);
+        }
+    }
+
+    #[test]
+    fn test_compute_and_verify_returns_computed_root() {
+        let mut tree = HybridMerkleTree::new(4);
+        tree.add_leaves(&[Felt::from(1), Felt::from(2), Felt::from(3)])
+            .unwrap();
+        let root = tree.root();
+        let (mut proof, _bits) = tree.path(LeafIndex(1)).unwrap();
+        assert_eq!(
+            compute_and_verify::<PoseidonHasher>(&root, &Felt::from(2), 1, &proof),
+            (true, root)
+        );
+
+        proof[2] = Felt::from(99);
+        let (valid, computed) =
+            compute_and_verify::<PoseidonHasher>(&root, &Felt::from(2), 1, &proof);
+        assert!(!valid);
+        assert_ne!(computed, root);
+        assert_eq!(
+            computed,
+            compute_merkle_root_rust::<PoseidonHasher>(Felt::from(2), 1, &proof)
+        );
+    }
+
+    #[test]
+    fn test_range_proof_of_prefix() {
+        let mut tree = HybridMerkleTree::new(4);
+        let leaves: Vec<Felt> = (1..=6).map(Felt::from).collect();
+        tree.add_leaves(&leaves).unwrap();
+        let root = tree.root();
+
+        let proof = tree.range_proof(0, 3).unwrap();
+        // Leaf 3, then the node over leaves 4..8.
+        assert_eq!(proof.siblings.len(), 2);
+        assert!(verify_range_proof::<PoseidonHasher>(
+            &root,
+            &leaves[..3],
+            0,
+            &proof
+        ));
+        assert!(!verify_range_proof::<PoseidonHasher>(
+            &root,
+            &leaves[..2],
+            0,
+            &proof
+        ));
+        assert!(!verify_range_proof::<PoseidonHasher>(
+            &root,
+            &leaves[1..4],
+            1,
+            &proof
+        ));
+
+        let middle = tree.range_proof(1, 5).unwrap();
+        assert!(verify_range_proof::<PoseidonHasher>(
+            &root,
+            &leaves[1..5],
+            1,
+            &middle
+        ));
+        assert_eq!(
+            tree.range_proof(3, 3),
+            Err(MerkleError::InvalidRange { start: 3, end: 3 })
+        );
+        assert_eq!(
+            tree.range_proof(4, 7),
+            Err(MerkleError::LeafNotFound { index: 6, len: 6 })
+        );
+    }
+
+    #[test]
+    fn test_verify_batch_mask() {
+        let mut tree = HybridMerkleTree::new(4);
+        let leaves: Vec<Felt> = (1..=6).map(|i| Felt::from(i as u32)).collect();
+        tree.add_leaves(&leaves).unwrap();
+        let mut items: Vec<(Felt, usize, Vec<Felt>)> = leaves
+            .iter()
+            .enumerate()
+            .map(|(index, leaf)| (*leaf, index, tree.path(LeafIndex(index)).unwrap().0))
+            .collect();
+        items[1].0 = Felt::from(99);
+        items[3].2[1] = Felt::from(99);
+        items[4].2.truncate(1);
+
+        assert_eq!(
+            verify_batch::<PoseidonHasher>(&tree.root(), &items, 4),
+            [true, false, true, false, false, true]
+        );
+    }
+
+    #[test]
+    fn test_sorted_sibling_order_ignores_sides() {
+        let sorted = |leaves: &[Felt]| {
+            let mut tree = HybridMerkleTree::builder()
+                .height(3)
+                .sibling_order(SiblingOrder::Sorted)
+                .build();
+            tree.add_leaves(leaves).unwrap();
+            tree
+        };
+        let (a, b, c) = (Felt::from(1), Felt::from(2), Felt::from(3));
+        let tree = sorted(&[a, b, c]);
+        assert_eq!(tree.sibling_order(), SiblingOrder::Sorted);
+        assert_eq!(tree.root(), sorted(&[b, a, c]).root());
+        assert_ne!(
+            HybridMerkleTree::from_leaves(3, &[a, b, c]).unwrap().root(),
+            HybridMerkleTree::from_leaves(3, &[b, a, c]).unwrap().root()
+        );
+
+        let null = default_null_base();
+        let expected = hash_sorted::<PoseidonHasher>(
+            &hash_sorted::<PoseidonHasher>(&a, &b),
+            &hash_sorted::<PoseidonHasher>(&c, &null),
+        );
+        assert_eq!(tree.root(), expected);
+        for index in 0..3 {
+            let proof = tree.proof(LeafIndex(index)).unwrap();
+            assert!(proof.verify_sorted(&tree.root()));
+        }
+        assert_eq!(
+            compute_merkle_root_sorted::<PoseidonHasher>(
+                c,
+                &[null, hash_sorted::<PoseidonHasher>(&b, &a)]
+            ),
+            tree.root()
+        );
+    }
+
+    #[test]
+    fn test_left_and_right_padding() {
+        let leaves = [Felt::from(1), Felt::from(2), Felt::from(3)];
+        let mut right = HybridMerkleTree::builder().height(3).build();
+        let mut left = HybridMerkleTree::builder()
+            .height(3)
+            .padding(Padding::Left)
+            .build();
+        right.add_leaves(&leaves).unwrap();
+        left.add_leaves(&leaves).unwrap();
+        assert_ne!(left.root(), right.root());
+        assert_eq!(left.position(LeafIndex(0)), 3);
+
+        for tree in [&right, &left] {
+            for (index, leaf) in leaves.iter().enumerate() {
+                let proof = tree.proof(LeafIndex(index)).unwrap();
+                assert!(proof.verify(&tree.root()));
+                assert_eq!(
+                    compute_merkle_root_rust::<PoseidonHasher>(
+                        *leaf,
+                        tree.position(LeafIndex(index)),
+                        &proof.siblings
+                    ),
+                    tree.root()
+                );
+            }
+            let positions: Vec<usize> = [0, 2]
+                .iter()
+                .map(|&i| tree.position(LeafIndex(i)))
+                .collect();
+            let proof = tree.multi_path(&[0, 2]).unwrap();
+            assert!(verify_multi_proof::<PoseidonHasher>(
+                &tree.root(),
+                &[leaves[0], leaves[2]],
+                &positions,
+                &proof
+            ));
+        }
+
+        // Left padding mirrors the tree: the leaves sit at the right end, nulls on the left.
+        let null = default_null_base();
+        let mirrored =
+            HybridMerkleTree::from_leaves(3, &[null, leaves[2], leaves[1], leaves[0]]).unwrap();
+        assert_eq!(left.root(), mirrored.root());
+
+        left.update_leaf(LeafIndex(1), &Felt::from(7)).unwrap();
+        assert!(left.proof(LeafIndex(1)).unwrap().verify(&left.root()));
+    }
+
+    #[test]
+    fn test_empty_root() {
+        use crate::hasher::PedersenHasher;
+
+        let mut tree = HybridMerkleTree::new(5);
+        assert_eq!(tree.empty_root(), tree.root());
+        assert_eq!(HybridMerkleTree::empty_root_for(5), tree.root());
+        tree.add_leaf(&Felt::from(1)).unwrap();
+        assert_ne!(tree.empty_root(), tree.root());
+
+        let base = Felt::from(3);
+        let tree = HybridMerkleTree::<PedersenHasher>::with_hasher_and_null_base(4, base);
+        assert_eq!(tree.empty_root(), tree.root());
+        assert_ne!(
+            tree.empty_root(),
+            HybridMerkleTree::<PedersenHasher>::empty_root_for_with_hasher(4)
+        );
+    }
+
+    #[test]
+    fn test_root_bytes_and_hex() {
+        let mut tree = HybridMerkleTree::new(3);
+        tree.add_leaf(&Felt::from(1)).unwrap();
+        assert_eq!(tree.root_bytes(), tree.root().to_bytes_be());
+        assert_eq!(
+            Felt::from_hex(&tree.root_hex()).unwrap(),
+            Felt::from_bytes_be(&tree.root_bytes())
+        );
+        assert_eq!(tree.root_hex().len(), 66);
+    }
+
+    #[test]
+    fn test_leaves_iterator() {
+        let leaves: Vec<Felt> = (1..=3).map(|i| Felt::from(i as u32)).collect();
+        let mut tree = HybridMerkleTree::new(3);
+        tree.add_leaves(&leaves).unwrap();
+        assert_eq!(tree.leaves().copied().collect::<Vec<_>>(), leaves);
+
+        let mut iterated = Vec::new();
+        for leaf in &tree {
+            iterated.push(*leaf);
+        }
+        assert_eq!(iterated, leaves);
+    }
+
+    #[test]
+    fn test_clear_resets_and_keeps_capacity() {
+        let leaves: Vec<Felt> = (1..=4).map(|i| Felt::from(i as u32)).collect();
+        let mut tree = HybridMerkleTree::new(3);
+        tree.add_leaves(&leaves).unwrap();
+        let allocated = tree.store.layers[0].capacity();
+
+        tree.clear();
+        assert!(tree.is_empty());
+        assert_eq!(tree.root(), tree.precomputed[2]);
+        assert_eq!(tree.store.layers[0].capacity(), allocated);
+
+        tree.add_leaves(&leaves[..3]).unwrap();
+        let fresh = HybridMerkleTree::from_leaves(3, &leaves[..3]).unwrap();
+        assert_eq!(tree.root(), fresh.root());
+        assert_eq!(tree.store.layers, fresh.store.layers);
+    }
+
+    #[test]
+    fn test_checkpoint_and_rollback() {
+        let leaves: Vec<Felt> = (1..=6).map(|i| Felt::from(i as u32)).collect();
+        let mut tree = HybridMerkleTree::new(4);
+        tree.add_leaves(&leaves[..3]).unwrap();
+        let checkpoint = tree.checkpoint();
+        let root = tree.root();
+
+        tree.add_leaves(&leaves[3..]).unwrap();
+        assert_ne!(tree.root(), root);
+        tree.rollback(checkpoint.clone()).unwrap();
+        assert_eq!(tree.root(), root);
+        assert_eq!(
+            tree.store.layers,
+            HybridMerkleTree::from_leaves(4, &leaves[..3])
+                .unwrap()
+                .store
+                .layers
+        );
+
+        // Appending after a rollback continues from the checkpointed state.
+        tree.add_leaf(&leaves[3]).unwrap();
+        assert_eq!(
+            tree.root(),
+            HybridMerkleTree::from_leaves(4, &leaves[..4])
+                .unwrap()
+                .root()
+        );
+
+        tree.clear();
+        assert_eq!(
+            tree.rollback(checkpoint),
+            Err(MerkleError::InvalidCheckpoint {
+                checkpoint_len: 3,
+                len: 0
+            })
+        );
+    }
+
+    #[test]
+    fn test_rollback_rejects_checkpoint_truncated_below() {
+        let leaves: Vec<Felt> = [1u32, 2, 3, 4, 30, 40, 50].map(Felt::from).to_vec();
+        let mut tree = HybridMerkleTree::new(4);
+        tree.add_leaves(&leaves[..4]).unwrap();
+        let checkpoint = tree.checkpoint();
+        tree.truncate(2).unwrap();
+        tree.add_leaves(&leaves[4..]).unwrap();
+        assert_eq!(
+            tree.rollback(checkpoint),
+            Err(MerkleError::InvalidCheckpoint {
+                checkpoint_len: 4,
+                len: 5
+            })
+        );
+        let mut expected = leaves[..2].to_vec();
+        expected.extend_from_slice(&leaves[4..]);
+        assert_eq!(
+            tree.root(),
+            HybridMerkleTree::from_leaves(4, &expected).unwrap().root()
+        );
+
+        // Shrinking to the checkpoint or above keeps it valid, as does an older shrink.
+        let early = tree.checkpoint();
+        tree.add_leaf(&Felt::from(60)).unwrap();
+        let late = tree.checkpoint();
+        tree.add_leaf(&Felt::from(70)).unwrap();
+        tree.truncate(6).unwrap();
+        tree.rollback(late.clone()).unwrap();
+        tree.rollback(early.clone()).unwrap();
+        assert_eq!(tree.len(), 5);
+        tree.add_leaves(&[Felt::from(80), Felt::from(90)]).unwrap();
+        assert!(tree.rollback(late).is_err(), "Rolled back below it");
+        tree.rollback(early).unwrap();
+        assert_eq!(
+            tree.root(),
+            HybridMerkleTree::from_leaves(4, &expected).unwrap().root()
+        );
+    }
+
+    #[test]
+    fn test_root_history() {
+        let mut tree = HybridMerkleTree::new(3).with_root_history();
+        let mut roots = vec![tree.root()];
+        for i in 1..=4 {
+            tree.add_leaf(&Felt::from(i as u32)).unwrap();
+            roots.push(tree.root());
+        }
+        for (len, root) in roots.iter().enumerate() {
+            assert_eq!(tree.root_at(len), Some(*root));
+        }
+        assert_eq!(tree.root_at(5), None);
+        assert_eq!(HybridMerkleTree::new(3).root_at(0), None);
+    }
+
+    #[test]
+    fn test_historical_path_verifies_against_older_root() {
+        let mut tree = HybridMerkleTree::new(3).with_root_history();
+        for i in 1..=4u32 {
+            tree.add_leaf(&Felt::from(i)).unwrap();
+        }
+        let proof = tree.historical_path(LeafIndex(0), 2).unwrap();
+        assert!(proof.verify(&tree.root_at(2).unwrap()));
+        assert!(!proof.verify(&tree.root()));
+        assert_eq!(
+            tree.historical_path(LeafIndex(0), 4),
+            tree.proof(LeafIndex(0))
+        );
+
+        for padding in [Padding::Right, Padding::Left] {
+            let leaves: Vec<Felt> = (1..=7u32).map(Felt::from).collect();
+            let mut tree = HybridMerkleTree::builder()
+                .height(4)
+                .padding(padding)
+                .build();
+            tree.add_leaves(&leaves).unwrap();
+            for at_len in 1..=leaves.len() {
+                let mut older = HybridMerkleTree::builder()
+                    .height(4)
+                    .padding(padding)
+                    .build();
+                older.add_leaves(&leaves[..at_len]).unwrap();
+                for index in 0..at_len {
+                    assert_eq!(
+                        tree.historical_path(LeafIndex(index), at_len),
+                        older.proof(LeafIndex(index))
+                    );
+                }
+            }
+        }
+
+        assert_eq!(
+            tree.historical_path(LeafIndex(2), 2),
+            Err(MerkleError::LeafNotFound { index: 2, len: 2 })
+        );
+        assert_eq!(
+            tree.historical_path(LeafIndex(0), 5),
+            Err(MerkleError::InvalidCheckpoint {
+                checkpoint_len: 5,
+                len: 4
+            })
+        );
+    }
+
+    #[test]
+    fn test_consistency_proofs_between_tree_states() {
+        let leaves: Vec<Felt> = (1..=8u32).map(Felt::from).collect();
+        let mut tree = HybridMerkleTree::new(4).with_root_history();
+        tree.add_leaves(&leaves).unwrap();
+        for new_len in 1..=leaves.len() {
+            let mut newer = HybridMerkleTree::new(4);
+            newer.add_leaves(&leaves[..new_len]).unwrap();
+            for old_len in 1..=new_len {
+                let proof = newer.consistency_proof(old_len).unwrap();
+                assert_eq!(proof.len(), 4);
+                let old_root = tree.root_at(old_len).unwrap();
+                assert!(verify_consistency::<PoseidonHasher>(
+                    &old_root,
+                    old_len,
+                    &newer.root(),
+                    new_len,
+                    &proof
+                ));
+                // A root from before the last append does not match the proof.
+                assert!(!verify_consistency::<PoseidonHasher>(
+                    &old_root,
+                    old_len,
+                    &tree.root_at(new_len - 1).unwrap(),
+                    new_len,
+                    &proof
+                ));
+            }
+        }
+
+        // Rewriting an old leaf breaks consistency with the old root.
+        let old_root = tree.root_at(3).unwrap();
+        let mut rewritten = tree.clone();
+        rewritten
+            .update_leaf(LeafIndex(1), &Felt::from(20))
+            .unwrap();
+        let proof = rewritten.consistency_proof(3).unwrap();
+        assert!(!verify_consistency::<PoseidonHasher>(
+            &old_root,
+            3,
+            &rewritten.root(),
+            8,
+            &proof
+        ));
+        let mut forged = tree.consistency_proof(3).unwrap();
+        forged[2] = Felt::from(99);
+        assert!(!verify_consistency::<PoseidonHasher>(
+            &old_root,
+            3,
+            &tree.root(),
+            8,
+            &forged
+        ));
+
+        assert_eq!(
+            tree.consistency_proof(0),
+            Err(MerkleError::InvalidRange { start: 0, end: 0 })
+        );
+        assert_eq!(
+            tree.consistency_proof(9),
+            Err(MerkleError::InvalidCheckpoint {
+                checkpoint_len: 9,
+                len: 8
+            })
+        );
+
+        let unsupported = [
+            HybridMerkleTree::builder()
+                .height(4)
+                .padding(Padding::Left)
+                .build(),
+            HybridMerkleTree::builder()
+                .height(4)
+                .sibling_order(SiblingOrder::Sorted)
+                .build(),
+            HybridMerkleTree::with_null_base(4, Felt::ZERO),
+        ];
+        for mut other in unsupported {
+            other.add_leaves(&leaves[..3]).unwrap();
+            assert!(matches!(
+                other.consistency_proof(2),
+                Err(MerkleError::UnsupportedConfiguration { .. })
+            ));
+        }
+    }
+
+    #[test]
+    fn test_verify_consistency_above_64_levels() {
+        let mut tree = HybridMerkleTree::new(70);
+        tree.add_leaf(&Felt::from(1)).unwrap();
+        let proof = tree.consistency_proof(1).unwrap();
+        assert_eq!(proof.len(), 70);
+        assert!(verify_consistency::<PoseidonHasher>(
+            &tree.root(),
+            1,
+            &tree.root(),
+            1,
+            &proof
+        ));
+        assert!(!verify_consistency::<PoseidonHasher>(
+            &tree.root(),
+            1,
+            &Felt::from(99),
+            1,
+            &proof
+        ));
+    }
+
+    #[test]
+    fn test_root_history_follows_rollback() {
+        let mut tree = HybridMerkleTree::new(3);
+        tree.add_leaf(&Felt::from(1)).unwrap();
+        let mut tree = tree.with_root_history();
+        assert_eq!(tree.root_at(0), None, "History starts when it is enabled");
+        let checkpoint = tree.checkpoint();
+        tree.add_leaf(&Felt::from(2)).unwrap();
+        tree.rollback(checkpoint).unwrap();
+        assert_eq!(tree.root_at(1), Some(tree.root()));
+        assert_eq!(tree.root_at(2), None);
+    }
+
+    #[test]
+    fn test_root_history_restarts_below_its_start() {
+        let leaves: Vec<Felt> = (1..=5u32).map(Felt::from).collect();
+        let expected = |len: usize| HybridMerkleTree::root_of(3, &leaves[..len]).unwrap();
+
+        let mut tree = HybridMerkleTree::from_leaves(3, &leaves[..4])
+            .unwrap()
+            .with_root_history();
+        tree.truncate(2).unwrap();
+        tree.add_leaf(&leaves[2]).unwrap();
+        assert_eq!(tree.root_at(1), None);
+        assert_eq!(tree.root_at(2), Some(expected(2)));
+        assert_eq!(tree.root_at(3), Some(expected(3)));
+        assert_eq!(tree.root_at(4), None);
+
+        let mut tree = HybridMerkleTree::from_leaves(3, &leaves[..1]).unwrap();
+        let checkpoint = tree.checkpoint();
+        tree.add_leaves(&leaves[1..3]).unwrap();
+        let mut tree = tree.with_root_history();
+        tree.add_leaf(&leaves[3]).unwrap();
+        tree.rollback(checkpoint).unwrap();
+        tree.add_leaf(&leaves[1]).unwrap();
+        assert_eq!(tree.root_at(1), Some(expected(1)));
+        assert_eq!(tree.root_at(2), Some(expected(2)));
+        assert_eq!(tree.root_at(3), None);
+    }
+
+    #[test]
+    fn test_index_of_and_contains() {
+        let leaves = [1u32, 2, 3, 2].map(Felt::from);
+        for tree in [
+            HybridMerkleTree::from_leaves(3, &leaves).unwrap(),
+            HybridMerkleTree::from_leaves(3, &leaves)
+                .unwrap()
+                .with_index_map(),
+        ] {
+            assert_eq!(tree.index_of(&Felt::from(3)), Some(2));
+            assert_eq!(
+                tree.index_of(&Felt::from(2)),
+                Some(1),
+                "Duplicates map to the first index"
+            );
+            assert!(tree.contains(&Felt::from(1)));
+            assert!(!tree.contains(&Felt::from(4)));
+        }
+    }
+
+    #[test]
+    fn test_snapshot_is_isolated_from_appends() {
+        let mut tree = HybridMerkleTree::with_store(4, SharedStore::new(4));
+        tree.add_leaves(&[1u32, 2, 3].map(Felt::from)).unwrap();
+        let snapshot = tree.snapshot();
+        let root = tree.root();
+
+        let reader = std::thread::spawn(move || {
+            let proof = snapshot.proof(LeafIndex(2)).unwrap();
+            assert!(snapshot.verify(&proof));
+            snapshot
+        });
+        tree.add_leaves(&[4u32, 5].map(Felt::from)).unwrap();
+        tree.update_leaf(LeafIndex(0), &Felt::from(9)).unwrap();
+        let snapshot = reader.join().unwrap();
+
+        assert_eq!(snapshot.root(), root);
+        assert_eq!(snapshot.len(), 3);
+        assert_eq!(snapshot.get_leaf(LeafIndex(0)), Some(Felt::from(1)));
+        assert_eq!(
+            snapshot.path(LeafIndex(3)),
+            Err(MerkleError::LeafNotFound { index: 3, len: 3 })
+        );
+        assert!(snapshot.verify(&snapshot.proof(LeafIndex(0)).unwrap()));
+        assert!(!snapshot.verify(&tree.proof(LeafIndex(0)).unwrap()));
+        assert_ne!(tree.root(), root);
+    }
+
+    #[test]
+    fn test_proof_for_value() {
+        let mut tree = HybridMerkleTree::new(4).with_index_map();
+        let leaves = [10u32, 20, 30, 40, 50].map(Felt::from);
+        tree.add_leaves(&leaves).unwrap();
+        for (index, leaf) in leaves.iter().enumerate() {
+            let proof = tree.proof_for_value(leaf).unwrap();
+            assert_eq!(proof.index, LeafIndex(index));
+            assert!(proof.verify(&tree.root()));
+        }
+        assert_eq!(tree.proof_for_value(&Felt::from(60)), None);
+    }
+
+    #[test]
+    fn test_reject_duplicates() {
+        let leaves = [1u32, 2, 3].map(Felt::from);
+        let mut permissive = HybridMerkleTree::new(4);
+        permissive.add_leaves(&leaves).unwrap();
+        assert_eq!(permissive.add_leaf(&leaves[1]), Ok(3));
+
+        let mut tree = HybridMerkleTree::new(4).with_reject_duplicates();
+        tree.add_leaves(&leaves).unwrap();
+        let duplicate = Err(MerkleError::DuplicateLeaf { index: 1 });
+        assert_eq!(tree.add_leaf(&leaves[1]), duplicate);
+        assert_eq!(
+            tree.add_leaf_with_proof(&leaves[1]).map(|(i, _)| i),
+            duplicate
+        );
+        assert_eq!(
+            tree.add_leaves(&[Felt::from(4), leaves[1]]),
+            duplicate.clone().map(|_| 0..0)
+        );
+        assert_eq!(
+            tree.add_leaves(&[Felt::from(4), Felt::from(5), Felt::from(4)]),
+            Err(MerkleError::DuplicateLeaf { index: 3 })
+        );
+        assert_eq!(
+            tree.update_leaf(LeafIndex(0), &leaves[1]),
+            duplicate.map(|_| ())
+        );
+        assert_eq!(tree.update_leaf(LeafIndex(1), &leaves[1]), Ok(()));
+        assert_eq!(
+            tree.merge(&HybridMerkleTree::from_leaves(4, &leaves[2..]).unwrap()),
+            Err(MerkleError::DuplicateLeaf { index: 2 })
+        );
+        assert_eq!(
+            tree.len(),
+            3,
+            "Rejected insertions leave the tree unchanged"
+        );
+        assert_eq!(
+            tree.root(),
+            HybridMerkleTree::from_leaves(4, &leaves).unwrap().root()
+        );
+        assert_eq!(tree.add_leaf(&Felt::from(4)), Ok(3));
+
+        // Duplicates are detected on tagged leaves.
+        let mut tagged = HybridMerkleTree::builder()
+            .height(3)
+            .leaf_domain(Felt::from(9))
+            .with_reject_duplicates()
+            .build();
+        tagged.add_leaf(&Felt::ONE).unwrap();
+        assert_eq!(
+            tagged.add_leaf(&Felt::ONE),
+            Err(MerkleError::DuplicateLeaf { index: 0 })
+        );
+    }
+
+    #[test]
+    fn test_index_map_follows_updates() {
+        let mut tree = HybridMerkleTree::new(3).with_index_map();
+        tree.add_leaves(&[1u32, 2, 3, 2].map(Felt::from)).unwrap();
+        tree.update_leaf(LeafIndex(1), &Felt::from(9)).unwrap();
+        assert_eq!(tree.index_of(&Felt::from(2)), Some(3));
+        assert_eq!(tree.index_of(&Felt::from(9)), Some(1));
+        tree.update_leaf(LeafIndex(3), &Felt::from(1)).unwrap();
+        assert_eq!(tree.index_of(&Felt::from(2)), None);
+        assert_eq!(tree.index_of(&Felt::from(1)), Some(0));
+
+        tree.clear();
+        assert!(!tree.contains(&Felt::from(1)));
+    }
+
+    #[test]
+    fn test_leaf_domain_separation() {
+        let domain = Felt::from(0xdead_u32);
+        let leaves: Vec<Felt> = (1..=3).map(|i| Felt::from(i as u32)).collect();
+        let mut tagged = HybridMerkleTree::new(3).with_leaf_domain(domain);
+        tagged.add_leaves(&leaves).unwrap();
+        let untagged = HybridMerkleTree::from_leaves(3, &leaves).unwrap();
+        assert_ne!(tagged.root(), untagged.root());
+
+        let (proof, _bits) = tagged.path(LeafIndex(1)).unwrap();
+        assert_eq!(
+            compute_merkle_root_with_domain::<PoseidonHasher>(leaves[1], Some(&domain), 1, &proof),
+            tagged.root()
+        );
+        assert_eq!(
+            tagged.get_leaf(LeafIndex(1)),
+            Some(PoseidonHasher::hash(&domain, &leaves[1]))
+        );
+
+        let root = tagged.root();
+        tagged.update_leaf(LeafIndex(1), &leaves[1]).unwrap();
+        assert_eq!(tagged.root(), root, "Updates are tagged too");
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_commit_records_root_commitments() {
+        let mut tree = HybridMerkleTree::new(4);
+        tree.add_leaf(&Felt::ONE).unwrap();
+        let first = tree.commit();
+        tree.add_leaves(&[Felt::TWO, Felt::THREE]).unwrap();
+        let second = tree.commit();
+
+        assert_eq!(tree.commitments(), [first.clone(), second.clone()]);
+        assert_eq!((first.leaf_count, second.leaf_count), (1, 3));
+        assert_ne!(first.root, second.root);
+        assert_eq!(second.root, tree.root());
+        assert!(second.time >= first.time);
+
+        tree.truncate(1).unwrap();
+        assert_eq!(
+            tree.commitments().len(),
+            2,
+            "Commitments outlive truncation"
+        );
+    }
+
+    #[test]
+    fn test_leaf_equal_to_null_base() {
+        let null = default_null_base();
+        let mut tree = HybridMerkleTree::new(4);
+        tree.add_leaves(&[Felt::ONE, null, Felt::TWO]).unwrap();
+        assert_eq!(tree.index_of(&null), Some(1));
+        assert_eq!(tree.clone().with_index_map().index_of(&null), Some(1));
+        assert_eq!(tree.get_leaf(LeafIndex(1)), Some(null));
+        let proof = tree.proof(LeafIndex(1)).unwrap();
+        assert_eq!(proof.leaf, null);
+        assert!(proof.verify(&tree.root()));
+
+        // Appending the null base leaves the root unchanged, but not the leaf count.
+        let mut tree = HybridMerkleTree::new(4);
+        let empty_root = tree.root();
+        tree.add_leaf(&null).unwrap();
+        assert_eq!(tree.root(), empty_root);
+        assert_eq!(tree.len(), 1);
+        assert!(tree.proof(LeafIndex(0)).unwrap().verify(&tree.root()));
+        assert_eq!(
+            tree.proof(LeafIndex(1)),
+            Err(MerkleError::LeafNotFound { index: 1, len: 1 })
+        );
+    }
+
+    #[test]
+    fn test_proof_cost() {
+        let cost = proof_cost(32);
+        assert_eq!(cost.siblings, 31);
+        assert_eq!(cost.poseidon_calls, 31);
+        assert_eq!(cost.bytes, 31 * 32);
+        assert_eq!(proof_cost(1).bytes, 0);
+
+        let tree = HybridMerkleTree::from_leaves(32, &[Felt::ONE]).unwrap();
+        let (siblings, _) = tree.path(LeafIndex(0)).unwrap();
+        assert_eq!(siblings.len(), cost.siblings);
+    }
+
+    #[test]
+    fn test_add_subtree() {
+        let leaves: Vec<Felt> = (1..=6).map(|i| Felt::from(i as u32)).collect();
+        let pair = HybridMerkleTree::from_leaves(2, &leaves[..2]).unwrap();
+        let mut tree = HybridMerkleTree::new(4);
+        assert_eq!(tree.add_subtree(&pair.root(), 1), Ok(0..2));
+        assert_eq!(
+            tree.root(),
+            HybridMerkleTree::from_leaves(4, &leaves[..2])
+                .unwrap()
+                .root()
+        );
+        assert_eq!(
+            tree.add_subtree(&Felt::ONE, 2),
+            Err(MerkleError::MisalignedSubtree {
+                index: 2,
+                leaves: 4
+            })
+        );
+
+        tree.add_leaves(&leaves[2..4]).unwrap();
+        assert_eq!(
+            tree.root(),
+            HybridMerkleTree::from_leaves(4, &leaves[..4])
+                .unwrap()
+                .root()
+        );
+        assert_eq!(
+            tree.add_subtree(&Felt::ONE, 3),
+            Err(MerkleError::TreeFull { capacity: 8 }),
+            "Only 4 leaves are free"
+        );
+        tree.add_leaves(&leaves[4..6]).unwrap();
+        let full = HybridMerkleTree::from_leaves(4, &leaves).unwrap();
+        assert_eq!(tree.root(), full.root());
+
+        // Grafted leaves are unknown, the others prove as usual.
+        assert_eq!(tree.get_leaf(LeafIndex(1)), None);
+        assert_eq!(
+            tree.path(LeafIndex(0)),
+            Err(MerkleError::PathUnavailable { index: 0 })
+        );
+        assert_eq!(tree.path(LeafIndex(5)), full.path(LeafIndex(5)));
+        assert_eq!(
+            tree.truncate(1),
+            Err(MerkleError::TruncateOutOfRange { new_len: 1, len: 6 })
+        );
+        tree.update_leaf(LeafIndex(3), &Felt::from(9)).unwrap();
+        tree.truncate(2).unwrap();
+        let grafted = HybridMerkleTree::from_leaves(4, &leaves[..2])
+            .unwrap()
+            .root();
+        assert_eq!(tree.root(), grafted);
+        tree.rehash();
+        assert_eq!(tree.root(), grafted, "Grafted roots survive a rehash");
+        assert!(
+            tree.store.layers[0].is_empty(),
+            "Grafted leaves are not stored"
+        );
+    }
+
+    #[test]
+    fn test_add_subtree_stores_only_its_root() {
+        let mut tree = HybridMerkleTree::new(64);
+        tree.add_subtree(&Felt::from(7), 20).unwrap();
+        assert_eq!(tree.node_count(), 64 - 20);
+        let leaves = [1u32, 2, 3].map(Felt::from);
+        let range = tree.add_leaves(&leaves).unwrap();
+        assert_eq!(range, (1 << 20)..(1 << 20) + 3);
+        assert_eq!(tree.leaves().copied().collect::<Vec<_>>(), leaves);
+        assert_eq!(tree.get_leaf(LeafIndex(1 << 20)), Some(leaves[0]));
+        for index in range {
+            assert!(tree.proof(LeafIndex(index)).unwrap().verify(&tree.root()));
+        }
+        tree.truncate(1 << 20).unwrap();
+        assert_eq!(tree.store.layers[0].len(), 0);
+        assert_eq!(tree.store.layers[20], vec![Felt::from(7)]);
+    }
+
+    #[cfg(not(feature = "std"))]
+    #[test]
+    fn test_root_without_std() {
+        let leaves: Vec<Felt> = (1..=3).map(|i| Felt::from(i as u32)).collect();
+        let tree = HybridMerkleTree::from_leaves(3, &leaves)
+            .unwrap()
+            .with_index_map();
+        assert_eq!(tree.root(), HybridMerkleTree::root_of(3, &leaves).unwrap());
+        assert_eq!(tree.index_of(&leaves[2]), Some(2));
+    }
 }
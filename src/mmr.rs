@@ -0,0 +1,240 @@
+use alloc::vec::Vec;
+use core::marker::PhantomData;
+
+use starknet_types_core::felt::Felt;
+
+use crate::error::MerkleError;
+use crate::hasher::{MerkleHasher, PoseidonHasher};
+
+/// Inclusion proof for the leaf at `position` of an `MmrAccumulator` of `len` leaves: the
+/// siblings of the leaf inside its peak, leaf level first, and every peak, largest first.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MmrProof {
+    pub leaf: Felt,
+    pub position: usize,
+    pub len: usize,
+    pub siblings: Vec<Felt>,
+    pub peaks: Vec<Felt>,
+}
+
+impl MmrProof {
+    /// Checks the proof against `root`. The peaks must match the layout `len` implies, and
+    /// the leaf must hash up to the peak covering `position`.
+    pub fn verify(&self, root: &Felt) -> bool {
+        self.verify_with_hasher::<PoseidonHasher>(root)
+    }
+
+    /// Same as `verify`, for a proof of an accumulator combining nodes with `H`.
+    pub fn verify_with_hasher<H: MerkleHasher>(&self, root: &Felt) -> bool {
+        if self.position >= self.len || self.peaks.len() != self.len.count_ones() as usize {
+            return false;
+        }
+        // Peaks cover consecutive leaves, one per bit of `len`, the highest bit first.
+        let mut start = 0;
+        let mut peak = 0;
+        for level in (0..usize::BITS as usize).rev() {
+            if self.len >> level & 1 == 0 {
+                continue;
+            }
+            let end = start + (1 << level);
+            if self.position < end {
+                if self.siblings.len() != level {
+                    return false;
+                }
+                break;
+            }
+            start = end;
+            peak += 1;
+        }
+        let mut index = self.position - start;
+        let mut node = self.leaf;
+        for sibling in &self.siblings {
+            node = if index % 2 == 1 {
+                H::hash(sibling, &node)
+            } else {
+                H::hash(&node, sibling)
+            };
+            index /= 2;
+        }
+        node == self.peaks[peak] && bag_peaks::<H>(self.len, &self.peaks) == *root
+    }
+}
+
+/// MmrAccumulator is a Merkle Mountain Range: an append-only list of perfect subtrees, one
+/// per bit set in the leaf count, whose roots (the peaks) are bagged into a single root.
+/// Unlike `HybridMerkleTree`, it has no fixed height, no capacity and no null padding; the
+/// peaks are the `HybridMerkleTree::subtree_roots` of a tree holding the same leaves.
+#[derive(Debug, Clone)]
+pub struct MmrAccumulator<H: MerkleHasher = PoseidonHasher> {
+    // Node `i` of layer `k` is the root of the perfect subtree over leaves `i << k..(i + 1) << k`.
+    layers: Vec<Vec<Felt>>,
+    hasher: PhantomData<H>,
+}
+
+impl MmrAccumulator {
+    pub fn new() -> Self {
+        Self::with_hasher()
+    }
+}
+
+impl Default for MmrAccumulator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<H: MerkleHasher> MmrAccumulator<H> {
+    /// Same as `new`, for an accumulator combining nodes with `H`.
+    pub fn with_hasher() -> Self {
+        Self {
+            layers: Vec::new(),
+            hasher: PhantomData,
+        }
+    }
+
+    /// Number of leaves appended so far.
+    pub fn len(&self) -> usize {
+        self.layers.first().map_or(0, Vec::len)
+    }
+
+    /// Returns `true` if no leaf has been appended yet.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Appends a leaf, merging the peaks it completes. Returns the position assigned to the
+    /// leaf, its index among the leaves.
+    pub fn append(&mut self, leaf: &Felt) -> usize {
+        let position = self.len();
+        let mut node = *leaf;
+        let mut level = 0;
+        loop {
+            if self.layers.len() == level {
+                self.layers.push(Vec::new());
+            }
+            let layer = &mut self.layers[level];
+            layer.push(node);
+            if layer.len() % 2 == 1 {
+                break;
+            }
+            node = H::hash(&layer[layer.len() - 2], &layer[layer.len() - 1]);
+            level += 1;
+        }
+        position
+    }
+
+    /// Returns the peaks, largest (leftmost) first.
+    pub fn peaks(&self) -> Vec<Felt> {
+        self.layers
+            .iter()
+            .rev()
+            .filter(|layer| layer.len() % 2 == 1)
+            .map(|layer| layer[layer.len() - 1])
+            .collect()
+    }
+
+    /// Returns the current root: the peaks bagged from right to left, then hashed with the
+    /// leaf count so that proofs commit to the peak layout. An empty accumulator's root is 0.
+    pub fn root(&self) -> Felt {
+        bag_peaks::<H>(self.len(), &self.peaks())
+    }
+
+    /// Generates the inclusion proof of the leaf at `position`.
+    pub fn prove(&self, position: usize) -> Result<MmrProof, MerkleError> {
+        let len = self.len();
+        if position >= len {
+            return Err(MerkleError::LeafNotFound {
+                index: position,
+                len,
+            });
+        }
+        // The peak covering `position` is at the level of the highest bit where `len` has a
+        // one and `position` a zero.
+        let peak_level = (len ^ position).ilog2() as usize;
+        let mut index = position;
+        let siblings = (0..peak_level)
+            .map(|level| {
+                let sibling = self.layers[level][index ^ 1];
+                index /= 2;
+                sibling
+            })
+            .collect();
+        Ok(MmrProof {
+            leaf: self.layers[0][position],
+            position,
+            len,
+            siblings,
+            peaks: self.peaks(),
+        })
+    }
+}
+
+/// Bags `peaks`, largest first, from right to left, and hashes the result with `len`.
+fn bag_peaks<H: MerkleHasher>(len: usize, peaks: &[Felt]) -> Felt {
+    match peaks.split_last() {
+        None => Felt::ZERO,
+        Some((last, rest)) => {
+            let bagged = rest
+                .iter()
+                .rev()
+                .fold(*last, |acc, peak| H::hash(peak, &acc));
+            H::hash(&Felt::from(len), &bagged)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::merkle::HybridMerkleTree;
+
+    #[test]
+    fn test_proofs_across_peak_layouts() {
+        let mut mmr = MmrAccumulator::new();
+        assert_eq!(mmr.root(), Felt::ZERO);
+        for i in 0..20u32 {
+            assert_eq!(mmr.append(&Felt::from(100 + i)), i as usize);
+            let root = mmr.root();
+            for position in 0..mmr.len() {
+                let proof = mmr.prove(position).unwrap();
+                assert_eq!(proof.leaf, Felt::from(100 + position as u32));
+                assert!(proof.verify(&root), "{} of {}", position, i);
+
+                let mut forged = proof.clone();
+                forged.leaf = Felt::from(7);
+                assert!(!forged.verify(&root));
+                let mut moved = proof.clone();
+                moved.len += 1;
+                assert!(!moved.verify(&root));
+            }
+        }
+    }
+
+    #[test]
+    fn test_peaks_match_subtree_roots() {
+        let leaves: Vec<Felt> = (1..=11u32).map(Felt::from).collect();
+        let mut mmr = MmrAccumulator::new();
+        for leaf in &leaves {
+            mmr.append(leaf);
+        }
+        let tree = HybridMerkleTree::from_leaves(5, &leaves).unwrap();
+        assert_eq!(mmr.peaks(), tree.subtree_roots());
+        assert_eq!(mmr.peaks().len(), 3);
+    }
+
+    #[test]
+    fn test_stale_proof_fails_after_append() {
+        let mut mmr = MmrAccumulator::new();
+        for i in 0..6u32 {
+            mmr.append(&Felt::from(i));
+        }
+        let proof = mmr.prove(5).unwrap();
+        mmr.append(&Felt::from(6));
+        assert!(!proof.verify(&mmr.root()));
+        assert!(mmr.prove(5).unwrap().verify(&mmr.root()));
+        assert_eq!(
+            mmr.prove(7),
+            Err(MerkleError::LeafNotFound { index: 7, len: 7 })
+        );
+    }
+}
@@ -0,0 +1,242 @@
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+use core::marker::PhantomData;
+
+use starknet_types_core::felt::Felt;
+
+use crate::error::MerkleError;
+use crate::hasher::{MerkleHasher, PoseidonHasher};
+use crate::helpers::{compute_root_from_bits, default_null_base, precomputed_hashes};
+
+/// Number of key bits a `SparseMerkleTree` walks, as for Starknet storage keys. The tree has
+/// `SPARSE_KEY_BITS + 1` levels, and keys must be below `2^SPARSE_KEY_BITS`.
+pub const SPARSE_KEY_BITS: usize = 251;
+
+/// Inclusion proof for the value stored at `key` in a `SparseMerkleTree`: the sibling of each
+/// level from the leaf upwards, the side of each level being given by the bits of `key`.
+/// A proof whose `value` is the tree's null base proves that nothing is stored at `key`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SparseMerkleProof {
+    pub key: Felt,
+    pub value: Felt,
+    pub siblings: Vec<Felt>,
+}
+
+impl SparseMerkleProof {
+    /// Checks the proof against `root`.
+    pub fn verify(&self, root: &Felt) -> bool {
+        self.verify_with_hasher::<PoseidonHasher>(root)
+    }
+
+    /// Same as `verify`, for a proof of a tree combining nodes with `H`.
+    pub fn verify_with_hasher<H: MerkleHasher>(&self, root: &Felt) -> bool {
+        if self.siblings.len() != SPARSE_KEY_BITS || self.key.bits() > SPARSE_KEY_BITS {
+            return false;
+        }
+        compute_root_from_bits::<H>(&self.value, &key_bits(&self.key), &self.siblings) == *root
+    }
+}
+
+/// SparseMerkleTree is a Merkle tree with one leaf per possible key, where leaf `key` is
+/// reached by walking the bits of `key` from the root. Only nodes above stored values are
+/// kept; every other subtree is empty and hashes to the precomputed null hash of its level.
+#[derive(Debug, Clone)]
+pub struct SparseMerkleTree<H: MerkleHasher = PoseidonHasher> {
+    precomputed: Vec<Felt>,
+    // Non-empty nodes, keyed by level and by the key bits above that level.
+    nodes: BTreeMap<(usize, [u64; 4]), Felt>,
+    hasher: PhantomData<H>,
+}
+
+impl SparseMerkleTree {
+    pub fn new() -> Self {
+        Self::with_hasher()
+    }
+
+    /// Creates an empty tree whose empty leaves hash from `base` instead of the default.
+    pub fn with_null_base(base: Felt) -> Self {
+        Self::with_hasher_and_null_base(base)
+    }
+}
+
+impl Default for SparseMerkleTree {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<H: MerkleHasher> SparseMerkleTree<H> {
+    /// Same as `new`, for a tree combining nodes with `H`.
+    pub fn with_hasher() -> Self {
+        Self::with_hasher_and_null_base(default_null_base())
+    }
+
+    /// Same as `with_null_base`, for a tree combining nodes with `H`.
+    pub fn with_hasher_and_null_base(base: Felt) -> Self {
+        Self {
+            precomputed: precomputed_hashes::<H>(SPARSE_KEY_BITS + 1, base),
+            nodes: BTreeMap::new(),
+            hasher: PhantomData,
+        }
+    }
+
+    /// Value of an absent key.
+    pub fn null_base(&self) -> Felt {
+        self.precomputed[0]
+    }
+
+    /// Returns the value stored at `key`, or the null base if there is none.
+    pub fn get(&self, key: &Felt) -> Felt {
+        self.node(0, key.to_le_digits())
+    }
+
+    /// Stores `value` at `key` and rehashes the path to the root. Storing the null base
+    /// removes the key, and the nodes that become empty are dropped.
+    pub fn update(&mut self, key: &Felt, value: &Felt) -> Result<(), MerkleError> {
+        check_key(key)?;
+        let mut position = key.to_le_digits();
+        let mut hash_val = *value;
+        for level in 0..=SPARSE_KEY_BITS {
+            if level > 0 {
+                let sibling = self.node(level - 1, flip_low_bit(position));
+                hash_val = if position[0] & 1 == 1 {
+                    H::hash(&sibling, &hash_val)
+                } else {
+                    H::hash(&hash_val, &sibling)
+                };
+                position = shift_right(position);
+            }
+            if hash_val == self.precomputed[level] {
+                self.nodes.remove(&(level, position));
+            } else {
+                self.nodes.insert((level, position), hash_val);
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the current tree root.
+    pub fn root(&self) -> Felt {
+        self.node(SPARSE_KEY_BITS, [0; 4])
+    }
+
+    /// Generates the proof of the value stored at `key`, which is the null base if the key
+    /// is absent.
+    pub fn get_proof(&self, key: &Felt) -> Result<SparseMerkleProof, MerkleError> {
+        check_key(key)?;
+        let mut position = key.to_le_digits();
+        let mut siblings = Vec::with_capacity(SPARSE_KEY_BITS);
+        for level in 0..SPARSE_KEY_BITS {
+            siblings.push(self.node(level, flip_low_bit(position)));
+            position = shift_right(position);
+        }
+        Ok(SparseMerkleProof {
+            key: *key,
+            value: self.get(key),
+            siblings,
+        })
+    }
+
+    /// Returns the node at `position` in layer `level`, or the null hash if it is empty.
+    fn node(&self, level: usize, position: [u64; 4]) -> Felt {
+        self.nodes
+            .get(&(level, position))
+            .copied()
+            .unwrap_or(self.precomputed[level])
+    }
+}
+
+/// Ensures `key` fits in `SPARSE_KEY_BITS` bits.
+fn check_key(key: &Felt) -> Result<(), MerkleError> {
+    if key.bits() > SPARSE_KEY_BITS {
+        return Err(MerkleError::KeyOutOfRange {
+            key: *key,
+            bits: SPARSE_KEY_BITS,
+        });
+    }
+    Ok(())
+}
+
+/// Side indicators of `key`, leaf level first, in the format of `compute_root_from_bits`.
+fn key_bits(key: &Felt) -> Vec<bool> {
+    key.to_bits_le()[..SPARSE_KEY_BITS].to_vec()
+}
+
+fn flip_low_bit(mut digits: [u64; 4]) -> [u64; 4] {
+    digits[0] ^= 1;
+    digits
+}
+
+fn shift_right(digits: [u64; 4]) -> [u64; 4] {
+    let mut shifted = [0; 4];
+    for i in 0..4 {
+        shifted[i] = digits[i] >> 1;
+        if i + 1 < 4 {
+            shifted[i] |= digits[i + 1] << 63;
+        }
+    }
+    shifted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_membership_proofs() {
+        let mut tree = SparseMerkleTree::new();
+        let empty_root = tree.root();
+        let entries = [
+            (Felt::from(0), Felt::from(10)),
+            (Felt::from(1), Felt::from(11)),
+            (Felt::from(u64::MAX), Felt::from(12)),
+            (Felt::TWO.pow(250u32) + Felt::from(7), Felt::from(13)),
+        ];
+        for (key, value) in &entries {
+            tree.update(key, value).unwrap();
+        }
+        assert_ne!(tree.root(), empty_root);
+        for (key, value) in &entries {
+            assert_eq!(tree.get(key), *value);
+            let proof = tree.get_proof(key).unwrap();
+            assert_eq!(proof.value, *value);
+            assert!(proof.verify(&tree.root()));
+
+            let mut forged = proof.clone();
+            forged.value = Felt::from(99);
+            assert!(!forged.verify(&tree.root()));
+        }
+    }
+
+    #[test]
+    fn test_non_membership_proof() {
+        let mut tree = SparseMerkleTree::new();
+        tree.update(&Felt::from(4), &Felt::from(40)).unwrap();
+        let absent = Felt::from(5);
+        let proof = tree.get_proof(&absent).unwrap();
+        assert_eq!(proof.value, tree.null_base());
+        assert!(proof.verify(&tree.root()));
+    }
+
+    #[test]
+    fn test_removing_keys_restores_empty_root() {
+        let mut tree = SparseMerkleTree::new();
+        let empty_root = tree.root();
+        tree.update(&Felt::from(3), &Felt::from(30)).unwrap();
+        tree.update(&Felt::from(8), &Felt::from(80)).unwrap();
+        let null = tree.null_base();
+        tree.update(&Felt::from(3), &null).unwrap();
+        tree.update(&Felt::from(8), &null).unwrap();
+        assert_eq!(tree.root(), empty_root);
+        assert!(tree.nodes.is_empty());
+    }
+
+    #[test]
+    fn test_rejects_keys_above_the_key_bits() {
+        let mut tree = SparseMerkleTree::new();
+        let key = Felt::TWO.pow(251u32);
+        let err = MerkleError::KeyOutOfRange { key, bits: 251 };
+        assert_eq!(tree.update(&key, &Felt::ONE), Err(err.clone()));
+        assert_eq!(tree.get_proof(&key), Err(err));
+    }
+}
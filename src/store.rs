@@ -0,0 +1,278 @@
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use starknet_types_core::felt::Felt;
+
+/// Storage for the nodes of a tree, addressed by level (0 for the leaves) and index within
+/// the level. Levels are dense: a level holding `len` nodes stores indices `0..len`.
+///
+/// Node `index` of `level` is the root of the subtree over leaves
+/// `index << level..(index + 1) << level`, so a tree of `n` leaves stores `ceil(n / 2^level)`
+/// nodes in `level`: nodes are only written for subtrees holding at least one leaf, and every
+/// level of a non-empty tree holds at least one node. Levels grow at their end on append and
+/// are overwritten in place on update, so a backend can lay each level out contiguously.
+/// The exception is `HybridMerkleTree::add_subtree`: nothing is stored below a grafted root,
+/// so the nodes after it sit that many indices lower in the levels below it.
+pub trait NodeStore {
+    /// Returns the node at `index` of `level`, if stored.
+    fn get(&self, level: usize, index: usize) -> Option<Felt>;
+
+    /// Stores `node` at `index` of `level`. `index` is at most `len(level)`: storing at
+    /// `len(level)` appends to the level, anything lower overwrites.
+    fn set(&mut self, level: usize, index: usize, node: Felt);
+
+    /// Number of nodes stored in `level`.
+    fn len(&self, level: usize) -> usize;
+
+    /// Drops every node of `level` from index `len` onwards.
+    fn truncate(&mut self, level: usize, len: usize);
+
+    /// Same as `get`, for stores that hold their nodes in memory and can read them without
+    /// side effects. Used by the invariant checks of debug builds, which skip the nodes this
+    /// returns `None` for, as the default does.
+    fn peek(&self, level: usize, index: usize) -> Option<Felt> {
+        let _ = (level, index);
+        None
+    }
+}
+
+/// VecStore keeps every level in memory as a `Vec`. It is the default store of a tree.
+/// The first node of a level is stored without spare capacity, so the upper levels of a small
+/// tree, which hold a single node each, cost one node of memory instead of a growth buffer.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct VecStore {
+    pub(crate) layers: Vec<Vec<Felt>>, // Each layer stores computed hashes.
+}
+
+impl VecStore {
+    /// Creates a store with `height` empty levels.
+    pub fn new(height: usize) -> Self {
+        Self {
+            layers: vec![Vec::new(); height],
+        }
+    }
+
+    /// Returns the nodes stored in `level`.
+    pub fn layer(&self, level: usize) -> &[Felt] {
+        &self.layers[level]
+    }
+
+    /// Number of nodes the levels have room for without reallocating, across all levels.
+    pub fn allocated_nodes(&self) -> usize {
+        self.layers.iter().map(Vec::capacity).sum()
+    }
+}
+
+impl NodeStore for VecStore {
+    fn get(&self, level: usize, index: usize) -> Option<Felt> {
+        self.layers[level].get(index).copied()
+    }
+
+    fn set(&mut self, level: usize, index: usize, node: Felt) {
+        let layer = &mut self.layers[level];
+        if index < layer.len() {
+            layer[index] = node;
+        } else {
+            if layer.capacity() == 0 {
+                layer.reserve_exact(1);
+            }
+            layer.push(node);
+        }
+    }
+
+    fn len(&self, level: usize) -> usize {
+        self.layers[level].len()
+    }
+
+    fn truncate(&mut self, level: usize, len: usize) {
+        self.layers[level].truncate(len);
+    }
+
+    fn peek(&self, level: usize, index: usize) -> Option<Felt> {
+        self.get(level, index)
+    }
+}
+
+/// Number of nodes in each chunk of a `SharedStore` level.
+pub const SHARED_CHUNK_LEN: usize = 1024;
+
+/// SharedStore keeps every level as fixed-size chunks behind `Arc`s, so cloning it only
+/// clones the chunk pointers. A chunk shared with a clone is copied the first time it is
+/// written, which on append is only the last chunk of each level. Used by
+/// `HybridMerkleTree::snapshot`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SharedStore {
+    layers: Vec<Vec<Arc<Vec<Felt>>>>,
+}
+
+impl SharedStore {
+    /// Creates a store with `height` empty levels.
+    pub fn new(height: usize) -> Self {
+        Self {
+            layers: vec![Vec::new(); height],
+        }
+    }
+}
+
+impl NodeStore for SharedStore {
+    fn get(&self, level: usize, index: usize) -> Option<Felt> {
+        self.layers[level]
+            .get(index / SHARED_CHUNK_LEN)?
+            .get(index % SHARED_CHUNK_LEN)
+            .copied()
+    }
+
+    fn set(&mut self, level: usize, index: usize, node: Felt) {
+        let chunks = &mut self.layers[level];
+        let (chunk, offset) = (index / SHARED_CHUNK_LEN, index % SHARED_CHUNK_LEN);
+        if chunk == chunks.len() {
+            chunks.push(Arc::new(Vec::with_capacity(SHARED_CHUNK_LEN)));
+        }
+        let chunk = Arc::make_mut(&mut chunks[chunk]);
+        if offset < chunk.len() {
+            chunk[offset] = node;
+        } else {
+            chunk.push(node);
+        }
+    }
+
+    fn len(&self, level: usize) -> usize {
+        match self.layers[level].last() {
+            Some(last) => (self.layers[level].len() - 1) * SHARED_CHUNK_LEN + last.len(),
+            None => 0,
+        }
+    }
+
+    fn truncate(&mut self, level: usize, len: usize) {
+        let chunks = &mut self.layers[level];
+        chunks.truncate(len.div_ceil(SHARED_CHUNK_LEN));
+        if let Some(last) = chunks.last_mut() {
+            let kept = len - (len - 1) / SHARED_CHUNK_LEN * SHARED_CHUNK_LEN;
+            if last.len() > kept {
+                Arc::make_mut(last).truncate(kept);
+            }
+        }
+    }
+
+    fn peek(&self, level: usize, index: usize) -> Option<Felt> {
+        self.get(level, index)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::cell::Cell;
+
+    use super::*;
+    use crate::merkle::{HybridMerkleTree, LeafIndex};
+
+    /// In-memory store counting the reads and writes made through `NodeStore`.
+    #[derive(Default)]
+    struct CountingStore {
+        inner: VecStore,
+        gets: Cell<usize>,
+        sets: usize,
+    }
+
+    impl NodeStore for CountingStore {
+        fn get(&self, level: usize, index: usize) -> Option<Felt> {
+            self.gets.set(self.gets.get() + 1);
+            self.inner.get(level, index)
+        }
+
+        fn set(&mut self, level: usize, index: usize, node: Felt) {
+            self.sets += 1;
+            self.inner.set(level, index, node);
+        }
+
+        fn len(&self, level: usize) -> usize {
+            self.inner.len(level)
+        }
+
+        fn truncate(&mut self, level: usize, len: usize) {
+            self.inner.truncate(level, len);
+        }
+    }
+
+    #[test]
+    fn test_counting_store_access_patterns() {
+        let store = CountingStore {
+            inner: VecStore::new(4),
+            ..Default::default()
+        };
+        let mut tree = HybridMerkleTree::with_store(4, store);
+        let mut reference = HybridMerkleTree::new(4);
+        for i in 1..=5 {
+            tree.add_leaf(&Felt::from(i as u32)).unwrap();
+            reference.add_leaf(&Felt::from(i as u32)).unwrap();
+        }
+        assert_eq!(tree.store().sets, 5 * 4, "Appends write one node per level");
+        assert_eq!(
+            tree.store().gets.get(),
+            0,
+            "Appends read from left_path only"
+        );
+        assert_eq!(tree.root(), reference.root());
+
+        let path = tree.path(LeafIndex(2)).unwrap();
+        assert_eq!(path, reference.path(LeafIndex(2)).unwrap());
+        assert_eq!(
+            tree.store().gets.get(),
+            3,
+            "A path reads one sibling per level"
+        );
+    }
+
+    #[test]
+    fn test_vec_store_allocates_one_node_for_single_node_levels() {
+        let mut tree = HybridMerkleTree::new(32);
+        assert_eq!(tree.store().allocated_nodes(), 0);
+        tree.add_leaf(&Felt::ONE).unwrap();
+        assert_eq!(tree.store().allocated_nodes(), 32);
+
+        tree.add_leaves(&[2u32, 3, 4, 5].map(Felt::from)).unwrap();
+        for level in 3..32 {
+            assert_eq!(tree.store().layers[level].capacity(), 1);
+        }
+        assert_eq!(
+            tree,
+            HybridMerkleTree::from_leaves(32, &tree.leaves().copied().collect::<Vec<_>>()).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_shared_store_matches_vec_store() {
+        let leaves: Vec<Felt> = (0..2500u32).map(Felt::from).collect();
+        let mut shared = HybridMerkleTree::with_store(13, SharedStore::new(13));
+        shared.add_leaves(&leaves).unwrap();
+        let mut reference = HybridMerkleTree::from_leaves(13, &leaves).unwrap();
+        assert_eq!(shared.root(), reference.root());
+        assert_eq!(shared.store().len(0), 2500);
+        assert_eq!(
+            shared.path(LeafIndex(2049)),
+            reference.path(LeafIndex(2049))
+        );
+
+        let copy = shared.store().clone();
+        shared.truncate(2048).unwrap();
+        reference.truncate(2048).unwrap();
+        assert_eq!(shared.root(), reference.root());
+        assert_eq!(shared.store().len(0), 2048);
+        assert_eq!(copy.len(0), 2500, "Clones keep their chunks");
+    }
+
+    #[test]
+    fn test_with_store_resumes_from_stored_nodes() {
+        let mut tree = HybridMerkleTree::new(4);
+        tree.add_leaves(&[Felt::from(1), Felt::from(2), Felt::from(3)])
+            .unwrap();
+        let mut reopened = HybridMerkleTree::with_store(4, tree.store().clone());
+        assert_eq!(reopened, tree);
+        assert_eq!(reopened.path(LeafIndex(0)), tree.path(LeafIndex(0)));
+
+        reopened.add_leaf(&Felt::from(4)).unwrap();
+        tree.add_leaf(&Felt::from(4)).unwrap();
+        assert_eq!(reopened.root(), tree.root());
+    }
+}
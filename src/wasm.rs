@@ -0,0 +1,58 @@
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+use wasm_bindgen::prelude::*;
+
+use crate::helpers::{check_height, parse_felt};
+use crate::merkle::{HybridMerkleTree, LeafIndex};
+
+/// Browser-facing wrapper around `HybridMerkleTree`. Felts cross the JS boundary as hex
+/// strings, since JS numbers cannot hold 252-bit values.
+#[wasm_bindgen]
+pub struct WasmMerkleTree {
+    inner: HybridMerkleTree,
+}
+
+#[wasm_bindgen]
+impl WasmMerkleTree {
+    /// Creates an empty tree, throwing instead of panicking on height 0.
+    #[wasm_bindgen(constructor)]
+    pub fn new(height: usize) -> Result<WasmMerkleTree, JsError> {
+        check_height(height).map_err(|err| JsError::new(&err.to_string()))?;
+        Ok(WasmMerkleTree {
+            inner: HybridMerkleTree::new(height),
+        })
+    }
+
+    /// Parses `leaf` with `parse_felt` (`0x` hex or decimal), inserts it and returns its index.
+    pub fn add_leaf(&mut self, leaf: &str) -> Result<usize, JsError> {
+        let leaf = parse_felt(leaf).map_err(|err| JsError::new(&err.to_string()))?;
+        self.inner
+            .add_leaf(&leaf)
+            .map_err(|err| JsError::new(&err.to_string()))
+    }
+
+    /// Returns the current root as a `0x`-prefixed 64-char hex string.
+    pub fn root_hex(&self) -> String {
+        self.inner.root_hex()
+    }
+
+    /// Returns the path of leaf `index` as `{"siblings": ["0x..", ...], "is_right": [..]}`.
+    pub fn path_json(&self, index: usize) -> Result<String, JsError> {
+        let (siblings, is_right) = self
+            .inner
+            .path(LeafIndex(index))
+            .map_err(|err| JsError::new(&err.to_string()))?;
+        let siblings: Vec<String> = siblings
+            .iter()
+            .map(|sibling| format!("\"{}\"", sibling.to_fixed_hex_string()))
+            .collect();
+        let is_right: Vec<String> = is_right.iter().map(|bit| bit.to_string()).collect();
+        Ok(format!(
+            "{{\"siblings\":[{}],\"is_right\":[{}]}}",
+            siblings.join(","),
+            is_right.join(",")
+        ))
+    }
+}
@@ -0,0 +1,100 @@
+//! Known-answer tests pinning roots and proofs to fixed values, so that a change to the
+//! hashing order, endianness or null padding is caught.
+//!
+//! The height-2 vector is Starknet's Poseidon test vector (as used by cairo-lang and
+//! `starknet-types-core`): a two-leaf tree over `(x, y)` must have root `Poseidon(x, y)`.
+//! The height-8 and height-32 vectors were generated without this crate or
+//! `starknet-types-core`, by `python3 tests/vectors/poseidon_reference.py` (CPython 3.11.7,
+//! standard library only). That script implements Poseidon from cairo-lang's
+//! `poseidon_utils.py` parameters, checks it against the height-2 vector, and reduces each
+//! tree level by level, padding with the null hash of the level.
+
+use noir_merkle_tree::hasher::PoseidonHasher;
+use noir_merkle_tree::helpers::{compute_merkle_root_rust, verify_proof};
+use noir_merkle_tree::merkle::{HybridMerkleTree, LeafIndex};
+use starknet_types_core::felt::Felt;
+
+fn felt(hex: &str) -> Felt {
+    Felt::from_hex(hex).unwrap()
+}
+
+fn assert_root(height: usize, leaves: &[Felt], expected: &str) {
+    let expected = felt(expected);
+    let mut tree = HybridMerkleTree::new(height);
+    tree.add_leaves(leaves).unwrap();
+    assert_eq!(tree.root(), expected, "incremental root, height {}", height);
+    assert_eq!(
+        HybridMerkleTree::root_of(height, leaves).unwrap(),
+        expected,
+        "streamed root, height {}",
+        height
+    );
+}
+
+#[test]
+fn test_height_1_root_is_the_leaf() {
+    let leaf = felt("0x03d937c035c878245caf64531a5756109c53068da139362728feb561405371cb");
+    assert_root(
+        1,
+        &[leaf],
+        "0x03d937c035c878245caf64531a5756109c53068da139362728feb561405371cb",
+    );
+}
+
+#[test]
+fn test_height_2_matches_starknet_poseidon_vector() {
+    let x = felt("0x03d937c035c878245caf64531a5756109c53068da139362728feb561405371cb");
+    let y = felt("0x0208a0a10250e382e1e4bbe2880906c2791bf6275695e02fbbc6aeff9cd8b31a");
+    assert_root(
+        2,
+        &[x, y],
+        "0x67c6a2e2d0c7867f97444ae17956dbc89d40ad22255bb06f5f6c515958926ed",
+    );
+}
+
+#[test]
+fn test_height_8_root_and_proof() {
+    let leaves: Vec<Felt> = (1..=5u32).map(Felt::from).collect();
+    let root = "0x032243876a17bd3aeb21d5d024fa995bd89f63270c764d845666a3fc3ef58570";
+    assert_root(8, &leaves, root);
+
+    let proof: Vec<Felt> = [
+        "0x0293d3e8a80f400daaaffdd5932e2bcc8814bab8f414a75dcacf87318f8b14c5",
+        "0x0296ec483967ad3fbe3407233db378b6284cc1fcc78d62457b97a4be6744ad0d",
+        "0x037c93a8507ea3cf33567ae2c6c33a0d86b997edcfc3b87280d9a572b2cde39b",
+        "0x033883305ab0df1ab7610153578a4d510b845841b84d90ed993133ce4ce8f827",
+        "0x040e4093fe5af73becf6507f475a529a78e49f604539ea5f3547059b5e7f1076",
+        "0x055dac7437527a89b6c03ecb7141193e30a38f87324f3da22f3b8ce7411a88cd",
+        "0x01ec859a19ca9ab8d8663eb85a09cfb902326fc14b3a2121569ed2847a9c22bf",
+    ]
+    .map(felt)
+    .to_vec();
+    let tree = HybridMerkleTree::from_leaves(8, &leaves).unwrap();
+    assert_eq!(tree.path(LeafIndex(4)).unwrap().0, proof);
+    assert_eq!(
+        compute_merkle_root_rust::<PoseidonHasher>(leaves[4], 4, &proof),
+        felt(root)
+    );
+    assert_eq!(
+        verify_proof::<PoseidonHasher>(&felt(root), &leaves[4], 4, &proof, 8),
+        Ok(true)
+    );
+}
+
+#[test]
+fn test_height_32_roots() {
+    assert_root(
+        32,
+        &[],
+        "0x0545daa9fba7052a85749f7fb5d19cc85a243e6c5ffd5ac5e6a8dab2937cd0ef",
+    );
+    assert_root(
+        32,
+        &[
+            Felt::from(0xaau32),
+            Felt::from(0xbbu32),
+            Felt::from(0xccu32),
+        ],
+        "0x01934cf4a21342cdbe467416c8cbead24cbaeee0e610eb1ed73b7604d918997d",
+    );
+}
@@ -0,0 +1,80 @@
+"""Regenerates the known-answer vectors of tests/vectors.rs without this crate.
+
+Poseidon is implemented from scratch with the parameters of cairo-lang's
+starkware/cairo/common/poseidon_utils.py: the Hades permutation over the Stark field, width 3,
+8 full and 83 partial rounds, the x^3 S-box, the [[3, 1, 1], [1, -1, 1], [1, 1, -2]] MDS
+matrix and round constants sha256("Hades{i}") mod p. hash(x, y) is the first element of
+hades([x, y, 2]). The script first checks Starknet's two-element test vector, then prints the
+roots and the proof of the height-8 and height-32 tests, computed by a naive level-by-level
+reduction.
+
+Run with `python3 tests/vectors/poseidon_reference.py`; it only needs the standard library.
+"""
+from hashlib import sha256
+
+P = 2**251 + 17 * 2**192 + 1
+WIDTH = 3
+FULL_ROUNDS, PARTIAL_ROUNDS = 8, 83
+MDS = [[3, 1, 1], [1, -1, 1], [1, 1, -2]]
+ROUND_KEYS = [
+    [int(sha256(f"Hades{WIDTH * i + j}".encode()).hexdigest(), 16) % P for j in range(WIDTH)]
+    for i in range(FULL_ROUNDS + PARTIAL_ROUNDS)
+]
+
+# DEFAULT_NULL_BASE_BYTES in src/helpers.rs.
+NULL_BASE = 0x0293D3E8A80F400DAAAFFDD5932E2BCC8814BAB8F414A75DCACF87318F8B14C5
+
+
+def hades(state):
+    for r, keys in enumerate(ROUND_KEYS):
+        state = [(s + k) % P for s, k in zip(state, keys)]
+        if FULL_ROUNDS // 2 <= r < FULL_ROUNDS // 2 + PARTIAL_ROUNDS:
+            state[-1] = pow(state[-1], 3, P)
+        else:
+            state = [pow(s, 3, P) for s in state]
+        state = [sum(m * s for m, s in zip(row, state)) % P for row in MDS]
+    return state
+
+
+def poseidon(x, y):
+    return hades([x, y, 2])[0]
+
+
+def layers(height, leaves):
+    """Yields each level below the root, padded to even length, and its null hash."""
+    null, layer = NULL_BASE, list(leaves)
+    for _ in range(height - 1):
+        if len(layer) % 2:
+            layer.append(null)
+        yield layer, null
+        layer = [poseidon(layer[i], layer[i + 1]) for i in range(0, len(layer), 2)]
+        null = poseidon(null, null)
+    yield layer, null
+
+
+def root(height, leaves):
+    layer, null = list(layers(height, leaves))[-1]
+    return layer[0] if layer else null
+
+
+def path(height, leaves, index):
+    siblings = []
+    for layer, null in list(layers(height, leaves))[:-1]:
+        siblings.append(layer[index ^ 1] if index ^ 1 < len(layer) else null)
+        index //= 2
+    return siblings
+
+
+def hex64(value):
+    return "0x%064x" % value
+
+
+x = 0x03D937C035C878245CAF64531A5756109C53068DA139362728FEB561405371CB
+y = 0x0208A0A10250E382E1E4BBE2880906C2791BF6275695E02FBBC6AEFF9CD8B31A
+assert poseidon(x, y) == 0x67C6A2E2D0C7867F97444AE17956DBC89D40AD22255BB06F5F6C515958926ED
+
+print("height 8, leaves 1..=5:", hex64(root(8, range(1, 6))))
+for sibling in path(8, range(1, 6), 4):
+    print("  sibling of leaf 4:", hex64(sibling))
+print("height 32, no leaves:", hex64(root(32, [])))
+print("height 32, leaves 0xaa, 0xbb, 0xcc:", hex64(root(32, [0xAA, 0xBB, 0xCC])))
@@ -0,0 +1,40 @@
+#![cfg(feature = "wasm")]
+
+use noir_merkle_tree::merkle::{HybridMerkleTree, LeafIndex};
+use noir_merkle_tree::wasm::WasmMerkleTree;
+use starknet_types_core::felt::Felt;
+use wasm_bindgen_test::wasm_bindgen_test;
+
+#[wasm_bindgen_test]
+fn test_wasm_tree_root_hex() {
+    let mut tree = WasmMerkleTree::new(3).unwrap();
+    assert_eq!(tree.add_leaf("0x1").unwrap(), 0);
+    assert_eq!(tree.add_leaf("0x2a").unwrap(), 1);
+
+    let expected = HybridMerkleTree::from_leaves(3, &[Felt::from(1), Felt::from(42)]).unwrap();
+    assert_eq!(tree.root_hex(), expected.root_hex());
+
+    let json = tree.path_json(1).unwrap();
+    let (siblings, _bits) = expected.path(LeafIndex(1)).unwrap();
+    assert!(json.starts_with(&format!(
+        "{{\"siblings\":[\"{}\",",
+        siblings[0].to_fixed_hex_string()
+    )));
+    assert!(json.ends_with("\"is_right\":[true,false]}"));
+}
+
+#[wasm_bindgen_test]
+fn test_wasm_tree_rejects_invalid_input() {
+    assert!(WasmMerkleTree::new(0).is_err());
+
+    let mut tree = WasmMerkleTree::new(3).unwrap();
+    assert!(tree.add_leaf("0xzz").is_err());
+    assert!(tree
+        .add_leaf("0xffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff")
+        .is_err());
+    assert_eq!(tree.add_leaf("42").unwrap(), 0);
+    assert_eq!(tree.root_hex(), {
+        let expected = HybridMerkleTree::from_leaves(3, &[Felt::from(42)]).unwrap();
+        expected.root_hex()
+    });
+}